@@ -0,0 +1,430 @@
+//! Nearest-neighbour search over post embeddings for `--smart-similar`.
+//!
+//! Small archives are compared all-pairs, which is exact and fast enough for a
+//! few thousand posts. Above [`EXACT_SEARCH_THRESHOLD`] an HNSW graph
+//! (Hierarchical Navigable Small World, Malkov & Yashunin 2016) is built
+//! instead, so each post only visits a few hundred others. Vectors are
+//! expected to be unit length; similarity is their dot product.
+
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Below this many vectors the exact all-pairs search is used.
+pub const EXACT_SEARCH_THRESHOLD: usize = 5_000;
+
+/// Links kept per node and layer.
+const MAX_LINKS: usize = 16;
+/// Candidate list size while inserting; larger builds a better graph, slower.
+const EF_CONSTRUCTION: usize = 64;
+/// Lower bound of the candidate list size while querying.
+const MIN_EF_SEARCH: usize = 64;
+/// Nodes whose links are searched in parallel against the graph built so far.
+/// Nodes of one batch cannot link to each other, so the graph is first grown
+/// one node at a time until it is much larger than a batch.
+const BUILD_BATCH: usize = 256;
+
+/// Returns up to `k` `(index, similarity)` pairs per vector, most similar
+/// first, never including the vector itself.
+pub fn nearest_neighbours(vectors: &[Vec<f32>], k: usize) -> Vec<Vec<(usize, f32)>> {
+    if vectors.len() < EXACT_SEARCH_THRESHOLD {
+        return exact_neighbours(vectors, k);
+    }
+
+    let index = Hnsw::build(vectors);
+    (0..vectors.len())
+        .into_par_iter()
+        .map(|i| index.search(&vectors[i], k, Some(i)))
+        .collect()
+}
+
+pub fn exact_neighbours(vectors: &[Vec<f32>], k: usize) -> Vec<Vec<(usize, f32)>> {
+    (0..vectors.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut scored: Vec<(usize, f32)> = (0..vectors.len())
+                .filter(|&j| j != i)
+                .map(|j| (j, dot(&vectors[i], &vectors[j])))
+                .collect();
+            if scored.len() > k {
+                scored.select_nth_unstable_by(k, by_similarity_desc);
+                scored.truncate(k);
+                scored.shrink_to_fit();
+            }
+            scored.sort_unstable_by(by_similarity_desc);
+            scored
+        })
+        .collect()
+}
+
+fn by_similarity_desc(a: &(usize, f32), b: &(usize, f32)) -> Ordering {
+    b.1.total_cmp(&a.1).then(a.0.cmp(&b.0))
+}
+
+/// Dot product over eight independent lanes, which the compiler can
+/// vectorize; a plain iterator sum has to add strictly in order.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut lanes = [0.0f32; 8];
+    let chunks_a = a.chunks_exact(8);
+    let chunks_b = b.chunks_exact(8);
+    let tail: f32 = chunks_a
+        .remainder()
+        .iter()
+        .zip(chunks_b.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in chunks_a.zip(chunks_b) {
+        for lane in 0..8 {
+            lanes[lane] += x[lane] * y[lane];
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// A heap entry ordered by similarity.
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    similarity: f32,
+    node: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub struct Hnsw<'a> {
+    vectors: &'a [Vec<f32>],
+    /// `links[node][layer]`; a node is present on layers `0..links[node].len()`.
+    links: Vec<Vec<Vec<u32>>>,
+    entry_point: u32,
+}
+
+impl<'a> Hnsw<'a> {
+    pub fn build(vectors: &'a [Vec<f32>]) -> Self {
+        let mut index = Hnsw {
+            vectors,
+            links: Vec::with_capacity(vectors.len()),
+            entry_point: 0,
+        };
+        if vectors.is_empty() {
+            return index;
+        }
+        index.links.push(vec![Vec::new(); node_level(0) + 1]);
+
+        let mut next = 1;
+        while next < vectors.len() {
+            let batch = if next < BUILD_BATCH * 8 {
+                1
+            } else {
+                BUILD_BATCH
+            };
+            let end = (next + batch).min(vectors.len());
+
+            let found: Vec<Vec<Vec<u32>>> = (next..end)
+                .into_par_iter()
+                .map(|node| index.find_links(node))
+                .collect();
+            for (node, layers) in (next..end).zip(found) {
+                index.connect(node as u32, layers);
+            }
+            next = end;
+        }
+        index
+    }
+
+    fn top_layer(&self) -> usize {
+        self.links[self.entry_point as usize].len() - 1
+    }
+
+    /// Chooses the links of a not yet inserted `node` on each of its layers.
+    fn find_links(&self, node: usize) -> Vec<Vec<u32>> {
+        let query = &self.vectors[node];
+        let level = node_level(node);
+        let mut entry = self.candidate(query, self.entry_point);
+
+        for layer in (level + 1..=self.top_layer()).rev() {
+            entry = self.greedy_closest(query, entry, layer);
+        }
+
+        let mut layers = vec![Vec::new(); level + 1];
+        let mut entries = vec![entry];
+        for layer in (0..=level.min(self.top_layer())).rev() {
+            let found = self.search_layer(query, &entries, EF_CONSTRUCTION, layer);
+            layers[layer] = select_diverse(self.vectors, &found, MAX_LINKS);
+            entries = found;
+        }
+        layers
+    }
+
+    /// Inserts `node` with its chosen links and adds the reverse links,
+    /// re-selecting a neighbour's links when it has too many.
+    fn connect(&mut self, node: u32, layers: Vec<Vec<u32>>) {
+        let vectors = self.vectors;
+        for (layer, neighbours) in layers.iter().enumerate() {
+            for &neighbour in neighbours {
+                let list = &mut self.links[neighbour as usize][layer];
+                list.push(node);
+                if list.len() > MAX_LINKS {
+                    let origin = &vectors[neighbour as usize];
+                    let mut scored: Vec<Candidate> = list
+                        .iter()
+                        .map(|&n| Candidate {
+                            similarity: dot(origin, &vectors[n as usize]),
+                            node: n,
+                        })
+                        .collect();
+                    scored.sort_unstable_by(|a, b| b.cmp(a));
+                    *list = select_diverse(vectors, &scored, MAX_LINKS);
+                }
+            }
+        }
+
+        let level = layers.len() - 1;
+        self.links.push(layers);
+        if level > self.top_layer() {
+            self.entry_point = node;
+        }
+    }
+
+    /// Returns up to `k` nearest nodes to `query`, skipping `exclude`.
+    pub fn search(&self, query: &[f32], k: usize, exclude: Option<usize>) -> Vec<(usize, f32)> {
+        if self.links.is_empty() {
+            return Vec::new();
+        }
+
+        let mut entry = self.candidate(query, self.entry_point);
+        for layer in (1..=self.top_layer()).rev() {
+            entry = self.greedy_closest(query, entry, layer);
+        }
+
+        let ef = (k + 1).max(MIN_EF_SEARCH);
+        self.search_layer(query, &[entry], ef, 0)
+            .into_iter()
+            .filter(|c| Some(c.node as usize) != exclude)
+            .take(k)
+            .map(|c| (c.node as usize, c.similarity))
+            .collect()
+    }
+
+    fn candidate(&self, query: &[f32], node: u32) -> Candidate {
+        Candidate {
+            similarity: dot(query, &self.vectors[node as usize]),
+            node,
+        }
+    }
+
+    /// Walks to the most similar node on `layer`, one improving hop at a time.
+    fn greedy_closest(&self, query: &[f32], mut current: Candidate, layer: usize) -> Candidate {
+        loop {
+            let best = self.links[current.node as usize][layer]
+                .iter()
+                .map(|&n| self.candidate(query, n))
+                .max()
+                .filter(|best| *best > current);
+            match best {
+                Some(best) => current = best,
+                None => return current,
+            }
+        }
+    }
+
+    /// Beam search on one layer; returns up to `ef` nodes, most similar first.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entries: &[Candidate],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entries.iter().map(|c| c.node).collect();
+        let mut frontier: BinaryHeap<Candidate> = entries.iter().copied().collect();
+        // Min-heap of the best `ef` found so far
+        let mut found: BinaryHeap<std::cmp::Reverse<Candidate>> =
+            entries.iter().map(|&c| std::cmp::Reverse(c)).collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = frontier.pop() {
+            let worst = found.peek().map(|r| r.0.similarity).unwrap_or(f32::MIN);
+            if current.similarity < worst && found.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.links[current.node as usize][layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let candidate = self.candidate(query, neighbour);
+                let worst = found.peek().map(|r| r.0.similarity).unwrap_or(f32::MIN);
+                if found.len() < ef || candidate.similarity > worst {
+                    frontier.push(candidate);
+                    found.push(std::cmp::Reverse(candidate));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut result: Vec<Candidate> = found.into_iter().map(|r| r.0).collect();
+        result.sort_unstable_by(|a, b| b.cmp(a));
+        result
+    }
+}
+
+/// Picks up to `limit` links from `candidates` (most similar first), skipping
+/// any candidate that is closer to an already picked link than to the origin,
+/// so links point in different directions instead of into one tight cluster.
+/// Skipped candidates fill the remaining slots.
+fn select_diverse(vectors: &[Vec<f32>], candidates: &[Candidate], limit: usize) -> Vec<u32> {
+    let mut picked: Vec<u32> = Vec::with_capacity(limit);
+    let mut skipped: Vec<u32> = Vec::new();
+    for candidate in candidates {
+        if picked.len() == limit {
+            break;
+        }
+        let vector = &vectors[candidate.node as usize];
+        let redundant = picked
+            .iter()
+            .any(|&p| dot(vector, &vectors[p as usize]) > candidate.similarity);
+        if redundant {
+            skipped.push(candidate.node);
+        } else {
+            picked.push(candidate.node);
+        }
+    }
+    let missing = limit - picked.len();
+    picked.extend(skipped.into_iter().take(missing));
+    picked
+}
+
+/// Layer of a node, drawn from the usual geometric distribution but derived
+/// from the node index so the graph (and thus the output) is reproducible.
+fn node_level(node: usize) -> usize {
+    let uniform = (splitmix64(node as u64) >> 11) as f64 / (1u64 << 53) as f64;
+    let level = -(1.0 - uniform).ln() / (MAX_LINKS as f64).ln();
+    level as usize
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exact_neighbours, splitmix64, Hnsw};
+    use rayon::prelude::*;
+    use std::time::Instant;
+
+    /// Unit vectors scattered around `clusters` random centres, like posts on a handful of topics.
+    fn clustered_vectors(count: usize, dims: usize, clusters: usize) -> Vec<Vec<f32>> {
+        let mut seed = 0;
+        let mut noise = move || {
+            seed += 1;
+            (splitmix64(seed) >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        };
+        let centres: Vec<Vec<f32>> = (0..clusters)
+            .map(|_| (0..dims).map(|_| noise()).collect())
+            .collect();
+
+        (0..count)
+            .map(|i| {
+                let v: Vec<f32> = centres[i % clusters]
+                    .iter()
+                    .map(|c| c + 0.5 * noise())
+                    .collect();
+                let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+                v.iter().map(|x| x / norm).collect()
+            })
+            .collect()
+    }
+
+    fn recall(vectors: &[Vec<f32>], index: &Hnsw, k: usize) -> f64 {
+        let exact = exact_neighbours(vectors, k);
+        let hits: usize = (0..vectors.len())
+            .into_par_iter()
+            .map(|i| {
+                let found = index.search(&vectors[i], k, Some(i));
+                found
+                    .iter()
+                    .filter(|(j, _)| exact[i].iter().any(|(e, _)| e == j))
+                    .count()
+            })
+            .sum();
+        hits as f64 / (vectors.len() * k) as f64
+    }
+
+    #[test]
+    fn hnsw_finds_nearly_all_true_neighbours() {
+        let vectors = clustered_vectors(3_000, 16, 20);
+        let index = Hnsw::build(&vectors);
+
+        assert!(recall(&vectors, &index, 10) > 0.95);
+    }
+
+    #[test]
+    fn exact_neighbours_are_sorted_and_exclude_self() {
+        let vectors = vec![vec![1.0, 0.0], vec![0.8, 0.6], vec![0.0, 1.0]];
+
+        let neighbours = exact_neighbours(&vectors, 5);
+
+        assert_eq!(neighbours[0], vec![(1, 0.8), (2, 0.0)]);
+        assert_eq!(neighbours[2][0].0, 1);
+    }
+
+    /// Compares the HNSW index against the exact search at archive scale.
+    /// Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn benchmark_hnsw_against_exact() {
+        let k = 10;
+        for count in [2_000, 10_000, 40_000] {
+            let vectors = clustered_vectors(count, 256, 200);
+
+            let start = Instant::now();
+            let exact = exact_neighbours(&vectors, k);
+            let exact_time = start.elapsed().as_secs_f64();
+
+            let start = Instant::now();
+            let index = Hnsw::build(&vectors);
+            let found: Vec<Vec<(usize, f32)>> = (0..count)
+                .into_par_iter()
+                .map(|i| index.search(&vectors[i], k, Some(i)))
+                .collect();
+            let hnsw_time = start.elapsed().as_secs_f64();
+
+            let hits: usize = (0..count)
+                .map(|i| {
+                    found[i]
+                        .iter()
+                        .filter(|(j, _)| exact[i].iter().any(|(e, _)| e == j))
+                        .count()
+                })
+                .sum();
+            println!(
+                "{:>6} posts: exact {:.2}s, hnsw {:.2}s, recall@{} {:.3}",
+                count,
+                exact_time,
+                hnsw_time,
+                k,
+                hits as f64 / (count * k) as f64
+            );
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Site settings read from `site.yaml`.
+//!
+//! Every setting has a default, so the file, and any section or key in it,
+//! may be left out. Unknown keys are rejected to catch typos.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_PATH: &str = "site.yaml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub llms: LlmsConfig,
+    pub robots: RobotsConfig,
+}
+
+/// Header of `llms.txt` and `llms-full.txt`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmsConfig {
+    pub title: String,
+    /// One-paragraph summary of the site, shown as the file's blockquote.
+    pub description: String,
+    /// Further markdown placed between the summary and the list of posts.
+    pub details: Option<String>,
+}
+
+impl Default for LlmsConfig {
+    fn default() -> Self {
+        Self {
+            title: "Sean Pedersen's Blog".to_string(),
+            description:
+                "Personal blog exploring Machine Learning, Data Privacy, Cybernetics and Memetics."
+                    .to_string(),
+            details: None,
+        }
+    }
+}
+
+/// What crawlers may fetch, and which pages search engines may index.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RobotsConfig {
+    /// Groups of `robots.txt`, in order.
+    pub rules: Vec<RobotsRule>,
+    /// Disallows the whole site to known AI training crawlers that no rule names.
+    pub block_ai_training: bool,
+    /// Pages marked `noindex`: canonical paths such as `/search/`, or path
+    /// prefixes ending in `*`.
+    pub noindex: Vec<String>,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        Self {
+            rules: vec![RobotsRule {
+                user_agents: vec!["*".to_string()],
+                allow: vec!["/".to_string()],
+                disallow: Vec::new(),
+            }],
+            block_ai_training: false,
+            noindex: Vec::new(),
+        }
+    }
+}
+
+/// One `robots.txt` group: the paths `user_agents` may and may not fetch.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RobotsRule {
+    pub user_agents: Vec<String>,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub disallow: Vec<String>,
+}
+
+/// Reads the site settings, falling back to the defaults if `path` does not exist.
+pub fn load_site_config(path: &Path) -> Result<SiteConfig> {
+    if !path.is_file() {
+        return Ok(SiteConfig::default());
+    }
+    let yaml = fs::read_to_string(path)?;
+    serde_yaml::from_str(&yaml).with_context(|| format!("Invalid site config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LlmsConfig, SiteConfig};
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let config: SiteConfig = serde_yaml::from_str("llms:\n  title: Notes\n").unwrap();
+        assert_eq!(config.llms.title, "Notes");
+        assert_eq!(config.llms.description, LlmsConfig::default().description);
+
+        assert!(serde_yaml::from_str::<SiteConfig>("llms:\n  titel: Notes\n").is_err());
+    }
+}
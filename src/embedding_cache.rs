@@ -0,0 +1,124 @@
+//! On-disk cache of post embeddings for `--smart-similar`.
+//!
+//! Entries are keyed by a SHA-256 of the model id and the exact text that was
+//! encoded, so an unchanged post is never re-encoded and switching models
+//! never returns stale vectors. Entries not requested during a build are
+//! dropped on save, which keeps the file from growing with deleted posts.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cache file, relative to the cache directory.
+pub const EMBEDDING_CACHE_FILE: &str = "embeddings.json";
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    entries: BTreeMap<String, Vec<f32>>,
+}
+
+pub struct EmbeddingCache {
+    path: PathBuf,
+    entries: BTreeMap<String, Vec<f32>>,
+    used: HashSet<String>,
+}
+
+pub fn cache_key(model_id: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+impl EmbeddingCache {
+    /// Loads the cache, starting empty when the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let entries = match fs::read_to_string(path) {
+            Ok(json) => match serde_json::from_str::<CacheFile>(&json) {
+                Ok(file) => file.entries,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Ignoring unreadable embedding cache {}: {}",
+                        path.display(),
+                        e
+                    );
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path: path.to_path_buf(),
+            entries,
+            used: HashSet::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Vec<f32>> {
+        let embedding = self.entries.get(key).cloned()?;
+        self.used.insert(key.to_string());
+        Some(embedding)
+    }
+
+    pub fn insert(&mut self, key: String, embedding: Vec<f32>) {
+        self.used.insert(key.clone());
+        self.entries.insert(key, embedding);
+    }
+
+    /// Writes only the entries used by this build.
+    pub fn save(mut self) -> Result<()> {
+        self.entries.retain(|key, _| self.used.contains(key));
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a sibling file first so an interrupted build never leaves a truncated cache
+        let tmp_path = self.path.with_extension("json.tmp");
+        let file = CacheFile {
+            entries: self.entries,
+        };
+        fs::write(&tmp_path, serde_json::to_string(&file)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, EmbeddingCache};
+
+    #[test]
+    fn cache_key_depends_on_model_and_text() {
+        let key = cache_key("model-a", "text");
+
+        assert_eq!(key, cache_key("model-a", "text"));
+        assert_ne!(key, cache_key("model-b", "text"));
+        assert_ne!(key, cache_key("model-a", "other text"));
+    }
+
+    #[test]
+    fn save_keeps_only_used_entries() {
+        let dir = std::env::temp_dir().join(format!("embedding-cache-{}", std::process::id()));
+        let path = dir.join("embeddings.json");
+
+        let mut cache = EmbeddingCache::load(&path);
+        cache.insert("kept".to_string(), vec![1.0, 2.0]);
+        cache.insert("dropped".to_string(), vec![3.0]);
+        cache.save().unwrap();
+
+        let mut cache = EmbeddingCache::load(&path);
+        assert_eq!(cache.get("kept"), Some(vec![1.0, 2.0]));
+        cache.save().unwrap();
+
+        let mut cache = EmbeddingCache::load(&path);
+        assert_eq!(cache.get("kept"), Some(vec![1.0, 2.0]));
+        assert_eq!(cache.get("dropped"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,763 @@
+//! Writes the RSS 2.0 (`rss.xml`), Atom 1.0 (`atom.xml`) and JSON Feed 1.1
+//! (`feed.json`) feeds, plus the optional `updates.xml` of revised posts.
+//!
+//! All are rendered from the same [`FeedEntry`] list, so every format
+//! carries identical titles, dates, tags, summaries and content. Feed dates
+//! come from the posts themselves, never from the time of the build.
+//!
+//! Few feed readers or email clients display MathML, so equations are sent
+//! as SVG images under `/math/`, with their LaTeX source as alt text.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::math_svg::mathml_to_svg;
+use crate::page_generation::Post;
+use crate::post_generation::extract_excerpt;
+use crate::urls::{absolute_url, SiteUrls, BASE_URL};
+
+/// Authority and date of the `tag:` URIs (RFC 4151) that identify entries;
+/// they must never change once a feed has been published.
+const TAG_URI_AUTHORITY: &str = "seanpedersen.github.io";
+const FEED_TITLE: &str = "Sean's Blog";
+const FEED_DESCRIPTION: &str = "Another place for thought infusion";
+const AUTHOR_NAME: &str = "Sean Pedersen";
+/// Output directory of the equation images, relative to the site root.
+const MATH_IMAGE_DIR: &str = "math";
+
+static NON_CONTENT_BLOCK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<(style|script)\b[^>]*>.*?</(?:style|script)\s*>").unwrap());
+static MATH_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<math\b[^>]*>.*?</math>").unwrap());
+static CODE_BLOCK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<pre\b[^>]*>.*?</pre>").unwrap());
+static SPAN_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"</?span\b[^>]*>").unwrap());
+static ALTTEXT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\balttext="([^"]*)""#).unwrap());
+static URL_ATTR_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b((?:data-)?(?:href|src|srcset))\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+/// How much of each post the feeds carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FeedContent {
+    /// The whole post.
+    Full,
+    /// Only an excerpt; readers follow the link for the rest.
+    Summary,
+}
+
+/// Feed settings, collected from the command line.
+pub struct FeedOptions {
+    pub content: FeedContent,
+    /// Newest entries kept in each feed; `None` keeps all.
+    pub max_items: Option<usize>,
+    /// Also write `updates.xml`, listing revised posts by modification date.
+    pub include_updates: bool,
+}
+
+/// Which date an RSS feed is ordered and stamped by.
+#[derive(Clone, Copy, PartialEq)]
+enum RssKind {
+    Published,
+    Updates,
+}
+
+/// One post as it appears in every feed format.
+struct FeedEntry<'a> {
+    post: &'a Post,
+    url: String,
+    /// Permanent id of the entry, independent of where the post is hosted.
+    tag_uri: String,
+    published: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    summary: String,
+    content_html: String,
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'static str,
+    home_page_url: String,
+    feed_url: String,
+    description: &'static str,
+    language: &'static str,
+    authors: Vec<JsonFeedAuthor>,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor {
+    name: &'static str,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_html: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_text: Option<&'a str>,
+    summary: &'a str,
+    date_published: String,
+    date_modified: String,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+pub fn build_feeds(
+    out_dir: &Path,
+    posts: &Arc<Vec<Post>>,
+    options: &FeedOptions,
+    urls: &SiteUrls,
+) -> Result<()> {
+    let start = Instant::now();
+
+    let mut math_images = BTreeMap::new();
+    let entries = feed_entries(posts, urls, &mut math_images);
+    let limit = options.max_items.unwrap_or(usize::MAX);
+
+    let mut newest: Vec<&FeedEntry> = entries.iter().collect();
+    newest.sort_by_key(|e| Reverse(e.published));
+    newest.truncate(limit);
+
+    let mut rss = BufWriter::new(File::create(out_dir.join("rss.xml"))?);
+    write_rss(&mut rss, &newest, RssKind::Published, options.content)?;
+    rss.flush()?;
+
+    let mut atom = BufWriter::new(File::create(out_dir.join("atom.xml"))?);
+    write_atom(&mut atom, &newest, options.content)?;
+    atom.flush()?;
+
+    let json = BufWriter::new(File::create(out_dir.join("feed.json"))?);
+    serde_json::to_writer_pretty(json, &json_feed(&newest, options.content, urls))?;
+
+    if options.content == FeedContent::Full && !math_images.is_empty() {
+        let math_dir = out_dir.join(MATH_IMAGE_DIR);
+        fs::create_dir_all(&math_dir)?;
+        for (name, svg) in &math_images {
+            fs::write(math_dir.join(format!("{}.svg", name)), svg)?;
+        }
+    }
+
+    if options.include_updates {
+        let updates = recently_updated(&entries, limit);
+        let mut file = BufWriter::new(File::create(out_dir.join("updates.xml"))?);
+        write_rss(&mut file, &updates, RssKind::Updates, options.content)?;
+        file.flush()?;
+    }
+
+    println!(
+        "✓ Generated RSS, Atom and JSON feeds in {:.2}s",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Posts modified after their publication, most recently modified first.
+fn recently_updated<'a>(entries: &'a [FeedEntry<'a>], limit: usize) -> Vec<&'a FeedEntry<'a>> {
+    let mut updated: Vec<&FeedEntry> = entries.iter().filter(|e| e.updated > e.published).collect();
+    updated.sort_by_key(|e| Reverse(e.updated));
+    updated.truncate(limit);
+    updated
+}
+
+/// Builds the entries of all feeds, collecting the equation images their
+/// content refers to into `math_images` (file stem -> SVG).
+fn feed_entries<'a>(
+    posts: &'a [Post],
+    urls: &SiteUrls,
+    math_images: &mut BTreeMap<String, String>,
+) -> Vec<FeedEntry<'a>> {
+    posts
+        .iter()
+        .map(|post| {
+            let published = parse_post_date(&post.date);
+            FeedEntry {
+                post,
+                url: urls.absolute(&urls.post(&post.id)),
+                tag_uri: tag_uri(&post.id, &post.date),
+                published: published.unwrap_or_default(),
+                updated: parse_post_date(&post.date_modified)
+                    .or(published)
+                    .unwrap_or_default(),
+                summary: extract_excerpt(&post.content_html),
+                content_html: feed_content(post, math_images, urls),
+            }
+        })
+        .collect()
+}
+
+/// Post HTML as feed readers should get it: no scripts or styles, no syntax
+/// highlighting spans, equations as images, and every link and image
+/// pointing at the live site.
+fn feed_content(
+    post: &Post,
+    math_images: &mut BTreeMap<String, String>,
+    urls: &SiteUrls,
+) -> String {
+    let content = NON_CONTENT_BLOCK_RE.replace_all(&post.content_html, "");
+    let content = replace_math(&content, math_images, urls);
+    let content = clean_content_for_rss(&content);
+    absolutize_urls(&content, &urls.post_base(&post.id))
+}
+
+/// Replaces every `<math>` element with an `<img>` of its SVG rendering,
+/// named by content hash, or with its LaTeX source if it cannot be rendered.
+fn replace_math(html: &str, math_images: &mut BTreeMap<String, String>, urls: &SiteUrls) -> String {
+    MATH_RE
+        .replace_all(html, |caps: &Captures| {
+            // Already escaped for an attribute, which is also valid as text
+            let latex = ALTTEXT_RE
+                .captures(&caps[0])
+                .map_or("", |alt| alt.get(1).unwrap().as_str());
+            let Some(image) = mathml_to_svg(&caps[0]) else {
+                return format!("<code>{}</code>", latex);
+            };
+
+            let name = format!("{:x}", Sha256::digest(image.svg.as_bytes()))[..16].to_string();
+            let img = format!(
+                r#"<img src="{}" alt="{}" width="{}" height="{}" style="vertical-align:-{:.1}px">"#,
+                urls.absolute(&format!("/{}/{}.svg", MATH_IMAGE_DIR, name)),
+                latex,
+                image.width,
+                image.height,
+                image.depth
+            );
+            math_images.insert(name, image.svg);
+            img
+        })
+        .into_owned()
+}
+
+/// Rewrites every `href`, `src` and `srcset` (including lazy-loading
+/// `data-` variants) in `html` to an absolute URL, resolving relative ones
+/// against `page_url`.
+fn absolutize_urls(html: &str, page_url: &str) -> String {
+    URL_ATTR_RE
+        .replace_all(html, |caps: &Captures| {
+            let (quote, value) = match (caps.get(2), caps.get(3)) {
+                (Some(double), _) => ('"', double.as_str()),
+                (_, Some(single)) => ('\'', single.as_str()),
+                _ => return caps[0].to_string(),
+            };
+            let value = if caps[1].eq_ignore_ascii_case("srcset") {
+                value
+                    .split(',')
+                    .map(|candidate| {
+                        let candidate = candidate.trim();
+                        match candidate.split_once(char::is_whitespace) {
+                            Some((url, descriptor)) => {
+                                format!("{} {}", absolute_url(url, page_url), descriptor.trim())
+                            }
+                            None => absolute_url(candidate, page_url),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            } else {
+                absolute_url(value, page_url)
+            };
+            format!("{}={}{}{}", &caps[1], quote, value, quote)
+        })
+        .into_owned()
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` post date.
+fn parse_post_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// `tag:` URI of a post, minted from its first publication date so that it
+/// survives domain moves and later edits.
+fn tag_uri(post_id: &str, date: &str) -> String {
+    format!("tag:{},{}:/posts/{}", TAG_URI_AUTHORITY, date, post_id)
+}
+
+/// RSS date of a UTC time, e.g. `Sat, 01 Jun 2024 00:00:00 GMT`.
+fn rfc822_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn write_rss(
+    out: &mut impl Write,
+    entries: &[&FeedEntry],
+    kind: RssKind,
+    content: FeedContent,
+) -> io::Result<()> {
+    let item_date = |entry: &FeedEntry| match kind {
+        RssKind::Published => entry.published,
+        RssKind::Updates => entry.updated,
+    };
+    let (title, path) = match kind {
+        RssKind::Published => (FEED_TITLE.to_string(), "rss.xml"),
+        RssKind::Updates => (format!("{} - Updated Posts", FEED_TITLE), "updates.xml"),
+    };
+    // Both channel dates follow the content, so rebuilding an unchanged site changes nothing
+    let pub_date = entries
+        .iter()
+        .map(|e| item_date(e))
+        .max()
+        .unwrap_or_default();
+    let last_build_date = entries.iter().map(|e| e.updated).max().unwrap_or_default();
+
+    // Write RSS header (match Node.js format with 2-space indentation)
+    write!(
+        out,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>{}</title>
+    <description>{}</description>
+    <link>{}</link>
+    <atom:link href="{}/{}" rel="self" type="application/rss+xml" />
+    <language>en</language>
+    <pubDate>{}</pubDate>
+    <lastBuildDate>{}</lastBuildDate>
+    <generator>Custom RSS Generator</generator>
+    <managingEditor>{}</managingEditor>
+    <webMaster>{}</webMaster>
+    <ttl>60</ttl>
+"#,
+        escape_xml(&title),
+        FEED_DESCRIPTION,
+        BASE_URL,
+        BASE_URL,
+        path,
+        rfc822_date(&pub_date),
+        rfc822_date(&last_build_date),
+        AUTHOR_NAME,
+        AUTHOR_NAME
+    )?;
+
+    for entry in entries {
+        // An update is a new item for readers, so it needs its own guid
+        let guid = match kind {
+            RssKind::Published => format!("<guid isPermaLink=\"true\">{}</guid>", entry.url),
+            RssKind::Updates => format!(
+                "<guid isPermaLink=\"false\">{}#{}</guid>",
+                entry.tag_uri,
+                entry.updated.format("%Y-%m-%d")
+            ),
+        };
+        write!(out, "    <item>\n      <title>{}</title>\n      <link>{}</link>\n      {}\n      <pubDate>{}</pubDate>\n      <author>{}</author>\n",
+            escape_xml(&entry.post.title), entry.url, guid,
+            rfc822_date(&item_date(entry)), AUTHOR_NAME)?;
+
+        // Write tags as categories
+        for tag in &entry.post.tags {
+            writeln!(out, "      <category>{}</category>", escape_xml(tag))?;
+        }
+
+        writeln!(
+            out,
+            "      <description>{}</description>",
+            escape_xml(&entry.summary)
+        )?;
+        if content == FeedContent::Full {
+            writeln!(
+                out,
+                "      <content:encoded><![CDATA[{}]]></content:encoded>",
+                entry.content_html
+            )?;
+        }
+        writeln!(out, "    </item>")?;
+    }
+
+    write!(out, "  </channel>\n</rss>")
+}
+
+fn write_atom(
+    out: &mut impl Write,
+    entries: &[&FeedEntry],
+    content: FeedContent,
+) -> io::Result<()> {
+    // The feed changes when its newest entry does, not on every build
+    let updated = entries.iter().map(|e| e.updated).max().unwrap_or_default();
+
+    write!(
+        out,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en">
+  <title>{}</title>
+  <subtitle>{}</subtitle>
+  <link href="{}/atom.xml" rel="self" type="application/atom+xml" />
+  <link href="{}/" rel="alternate" type="text/html" />
+  <id>{}/</id>
+  <updated>{}</updated>
+  <author>
+    <name>{}</name>
+  </author>
+  <generator>blog-builder</generator>
+"#,
+        escape_xml(FEED_TITLE),
+        FEED_DESCRIPTION,
+        BASE_URL,
+        BASE_URL,
+        BASE_URL,
+        updated.to_rfc3339(),
+        AUTHOR_NAME
+    )?;
+
+    for entry in entries {
+        write!(
+            out,
+            "  <entry>\n    <title>{}</title>\n    <link href=\"{}\" rel=\"alternate\" type=\"text/html\" />\n    <id>{}</id>\n    <published>{}</published>\n    <updated>{}</updated>\n",
+            escape_xml(&entry.post.title),
+            entry.url,
+            entry.tag_uri,
+            entry.published.to_rfc3339(),
+            entry.updated.to_rfc3339()
+        )?;
+        for tag in &entry.post.tags {
+            writeln!(out, "    <category term=\"{}\" />", escape_xml(tag))?;
+        }
+        writeln!(out, "    <summary>{}</summary>", escape_xml(&entry.summary))?;
+        if content == FeedContent::Full {
+            writeln!(
+                out,
+                "    <content type=\"html\">{}</content>",
+                escape_xml(&entry.content_html)
+            )?;
+        }
+        writeln!(out, "  </entry>")?;
+    }
+
+    write!(out, "</feed>")
+}
+
+fn json_feed<'a>(entries: &[&'a FeedEntry], content: FeedContent, urls: &SiteUrls) -> JsonFeed<'a> {
+    JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: FEED_TITLE,
+        home_page_url: format!("{}/", BASE_URL),
+        feed_url: format!("{}/feed.json", BASE_URL),
+        description: FEED_DESCRIPTION,
+        language: "en",
+        authors: vec![JsonFeedAuthor {
+            name: AUTHOR_NAME,
+            url: format!("{}/", BASE_URL),
+        }],
+        items: entries
+            .iter()
+            .map(|entry| JsonFeedItem {
+                id: &entry.tag_uri,
+                url: &entry.url,
+                title: &entry.post.title,
+                content_html: (content == FeedContent::Full).then_some(&*entry.content_html),
+                content_text: (content == FeedContent::Summary).then_some(&*entry.summary),
+                summary: &entry.summary,
+                date_published: entry.published.to_rfc3339(),
+                date_modified: entry.updated.to_rfc3339(),
+                tags: &entry.post.tags,
+                image: entry
+                    .post
+                    .icon
+                    .as_ref()
+                    .map(|icon| absolute_url(icon, &urls.post_base(&entry.post.id))),
+            })
+            .collect(),
+    }
+}
+
+fn clean_content_for_rss(html: &str) -> String {
+    // Remove the syntax highlighting spans of code blocks but keep their text.
+    // Only inside <pre>, so spans elsewhere (such as inline figures) stay closed
+    let mut result = CODE_BLOCK_RE
+        .replace_all(html, |caps: &Captures| {
+            SPAN_TAG_RE.replace_all(&caps[0], "").into_owned()
+        })
+        .into_owned();
+
+    // Replace remark-highlight divs with just the code block
+    // Match: <div class="remark-highlight"><pre class="language-X"><code class="language-X">
+    // Replace with: <pre><code>
+    let remark_re = Regex::new(r#"<div class="remark-highlight"><pre class="language-[^"]*"><code class="language-[^"]*">"#).unwrap();
+    result = remark_re.replace_all(&result, "<pre><code>").to_string();
+
+    // Close the remark-highlight div properly: </code></pre></div>
+    result = result.replace("</code></pre></div>", "</code></pre>");
+
+    result
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        absolutize_urls, feed_content, json_feed, parse_post_date, recently_updated, replace_math,
+        tag_uri, write_atom, write_rss, FeedContent, FeedEntry, RssKind,
+    };
+    use crate::math::to_mathml;
+    use crate::page_generation::Post;
+    use crate::urls::{SiteUrls, TrailingSlash};
+    use latex2mathml::DisplayStyle;
+    use std::collections::BTreeMap;
+
+    fn post() -> Post {
+        Post {
+            id: "hello".to_string(),
+            title: "Q&A".to_string(),
+            date: "2024-03-01".to_string(),
+            date_modified: "2024-05-02".to_string(),
+            tags: vec!["rust".to_string(), "web".to_string()],
+            icon: None,
+            content_html: "<p>Hi</p>".to_string(),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn tag_uri_is_minted_from_first_publication() {
+        assert_eq!(
+            tag_uri("hello", "2024-03-01"),
+            "tag:seanpedersen.github.io,2024-03-01:/posts/hello"
+        );
+    }
+
+    fn entry(post: &Post) -> FeedEntry<'_> {
+        FeedEntry {
+            post,
+            url: "https://seanpedersen.github.io/posts/hello".to_string(),
+            tag_uri: tag_uri(&post.id, &post.date),
+            published: parse_post_date(&post.date).unwrap(),
+            updated: parse_post_date(&post.date_modified).unwrap(),
+            summary: "Hi".to_string(),
+            content_html: "<p>Hi</p>".to_string(),
+        }
+    }
+
+    #[test]
+    fn atom_entry_has_dates_categories_and_escaped_content() {
+        let post = post();
+        let entry = entry(&post);
+
+        let mut out = Vec::new();
+        write_atom(&mut out, &[&entry], FeedContent::Full).unwrap();
+        let atom = String::from_utf8(out).unwrap();
+
+        assert!(atom.contains("<updated>2024-05-02T00:00:00+00:00</updated>\n  <author>"));
+        assert!(atom.contains("<title>Q&amp;A</title>"));
+        assert!(atom.contains("<published>2024-03-01T00:00:00+00:00</published>"));
+        assert!(atom.contains("<category term=\"rust\" />"));
+        assert!(atom.contains("<summary>Hi</summary>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Hi&lt;/p&gt;</content>"));
+    }
+
+    #[test]
+    fn json_feed_items_follow_version_1_1() {
+        let urls = SiteUrls::new(TrailingSlash::Never);
+        let mut post = post();
+        let entry = entry(&post);
+        let feed = serde_json::to_value(json_feed(&[&entry], FeedContent::Full, &urls)).unwrap();
+        let item = &feed["items"][0];
+
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["authors"][0]["name"], "Sean Pedersen");
+        assert_eq!(
+            item["id"],
+            "tag:seanpedersen.github.io,2024-03-01:/posts/hello"
+        );
+        assert_eq!(item["date_modified"], "2024-05-02T00:00:00+00:00");
+        assert_eq!(item["tags"], serde_json::json!(["rust", "web"]));
+        assert_eq!(item["content_html"], "<p>Hi</p>");
+        assert!(item.get("image").is_none());
+
+        post.icon = Some("/images/icons/rust.svg".to_string());
+        let entry = self::entry(&post);
+        let feed = serde_json::to_value(json_feed(&[&entry], FeedContent::Summary, &urls)).unwrap();
+        assert_eq!(feed["items"][0]["content_text"], "Hi");
+        assert!(feed["items"][0].get("content_html").is_none());
+        assert_eq!(
+            feed["items"][0]["image"],
+            "https://seanpedersen.github.io/images/icons/rust.svg"
+        );
+
+        for (icon, image) in [
+            (
+                "https://cdn.example.com/rust.svg",
+                "https://cdn.example.com/rust.svg",
+            ),
+            (
+                "icon.svg",
+                "https://seanpedersen.github.io/posts/hello/icon.svg",
+            ),
+        ] {
+            post.icon = Some(icon.to_string());
+            let entry = self::entry(&post);
+            let feed =
+                serde_json::to_value(json_feed(&[&entry], FeedContent::Full, &urls)).unwrap();
+            assert_eq!(feed["items"][0]["image"], image);
+        }
+    }
+
+    #[test]
+    fn relative_urls_become_absolute() {
+        let page = "https://seanpedersen.github.io/posts/hello/";
+        let html = concat!(
+            r#"<a href="/posts/other">a</a><a href='#intro'>b</a><img src="img/a.png">"#,
+            r#"<img data-src="../../images/b.webp"><a href="https://example.com/x">c</a>"#,
+            r#"<a href="mailto:me@example.com">d</a><img srcset="/s.webp 1x, s@2x.webp 2x">"#
+        );
+
+        assert_eq!(
+            absolutize_urls(html, page),
+            concat!(
+                r#"<a href="https://seanpedersen.github.io/posts/other">a</a>"#,
+                r#"<a href='https://seanpedersen.github.io/posts/hello/#intro'>b</a>"#,
+                r#"<img src="https://seanpedersen.github.io/posts/hello/img/a.png">"#,
+                r#"<img data-src="https://seanpedersen.github.io/images/b.webp">"#,
+                r#"<a href="https://example.com/x">c</a><a href="mailto:me@example.com">d</a>"#,
+                r#"<img srcset="https://seanpedersen.github.io/s.webp 1x, "#,
+                r#"https://seanpedersen.github.io/posts/hello/s@2x.webp 2x">"#
+            )
+        );
+    }
+
+    #[test]
+    fn feed_content_keeps_inline_figures_closed() {
+        let mut post = post();
+        post.content_html = concat!(
+            r#"<p>See <span class="figure"><img src="/images/plot.png" alt="Plot">"#,
+            r#"<span class="figcaption">The plot</span></span> below.</p>"#,
+            r#"<div class="remark-highlight"><pre class="language-rust"><code class="language-rust">"#,
+            r#"<span class="token keyword">fn</span> <span>main</span>()</code></pre></div>"#,
+        )
+        .to_string();
+
+        assert_eq!(
+            feed_content(
+                &post,
+                &mut BTreeMap::new(),
+                &SiteUrls::new(TrailingSlash::Never)
+            ),
+            concat!(
+                r#"<p>See <span class="figure"><img src="https://seanpedersen.github.io/images/plot.png" alt="Plot">"#,
+                r#"<span class="figcaption">The plot</span></span> below.</p>"#,
+                "<pre><code>fn main()</code></pre>",
+            )
+        );
+    }
+
+    #[test]
+    fn feed_content_keeps_nested_and_multiline_divs() {
+        let mut post = post();
+        post.content_html = concat!(
+            "<div\n  class=\"callout\"><div><p>inner</p></div>\n</div>",
+            "<script>track()</script><p>after <a href=\"/x\">x</a></p>"
+        )
+        .to_string();
+
+        assert_eq!(
+            feed_content(
+                &post,
+                &mut BTreeMap::new(),
+                &SiteUrls::new(TrailingSlash::Never)
+            ),
+            concat!(
+                "<div\n  class=\"callout\"><div><p>inner</p></div>\n</div>",
+                "<p>after <a href=\"https://seanpedersen.github.io/x\">x</a></p>"
+            )
+        );
+    }
+
+    #[test]
+    fn summary_rss_dates_come_from_the_posts() {
+        let post = post();
+        let entry = entry(&post);
+
+        let mut out = Vec::new();
+        write_rss(
+            &mut out,
+            &[&entry],
+            RssKind::Published,
+            FeedContent::Summary,
+        )
+        .unwrap();
+        let rss = String::from_utf8(out).unwrap();
+
+        // The channel and its item share one date format
+        assert_eq!(
+            rss.matches("<pubDate>Fri, 01 Mar 2024 00:00:00 GMT</pubDate>")
+                .count(),
+            2
+        );
+        assert!(rss.contains("<lastBuildDate>Thu, 02 May 2024 00:00:00 GMT</lastBuildDate>"));
+        assert!(rss.contains("<description>Hi</description>"));
+        assert!(!rss.contains("<content:encoded>"));
+    }
+
+    #[test]
+    fn updates_feed_lists_revised_posts_newest_first() {
+        let mut old = post();
+        old.id = "old".to_string();
+        old.date_modified = "2024-04-01".to_string();
+        let mut recent = post();
+        recent.id = "recent".to_string();
+        recent.date_modified = "2024-06-01".to_string();
+        let mut unchanged = post();
+        unchanged.date_modified = unchanged.date.clone();
+        let entries = vec![entry(&old), entry(&unchanged), entry(&recent)];
+
+        let updates = recently_updated(&entries, 10);
+        let ids: Vec<&str> = updates.iter().map(|e| e.post.id.as_str()).collect();
+        assert_eq!(ids, ["recent", "old"]);
+        assert_eq!(recently_updated(&entries, 1).len(), 1);
+
+        let mut out = Vec::new();
+        write_rss(&mut out, &updates, RssKind::Updates, FeedContent::Full).unwrap();
+        let rss = String::from_utf8(out).unwrap();
+        assert!(rss.contains("/updates.xml\" rel=\"self\""));
+        assert!(rss.contains(
+            "<guid isPermaLink=\"false\">tag:seanpedersen.github.io,2024-03-01:/posts/recent#2024-06-01</guid>"
+        ));
+        assert!(rss.contains("<pubDate>Sat, 01 Jun 2024 00:00:00 GMT</pubDate>"));
+    }
+
+    #[test]
+    fn math_becomes_images_with_latex_alt_text() {
+        let mathml = to_mathml("E_0 = mc^2", DisplayStyle::Inline).unwrap();
+        let mut images = BTreeMap::new();
+
+        let content = replace_math(
+            &format!("<p>Energy: {}.</p>", mathml),
+            &mut images,
+            &SiteUrls::new(TrailingSlash::Never),
+        );
+
+        assert_eq!(images.len(), 1);
+        let name = images.keys().next().unwrap();
+        assert!(content.starts_with(&format!(
+            r#"<p>Energy: <img src="https://seanpedersen.github.io/math/{}.svg" alt="E_0 = mc^2" "#,
+            name
+        )));
+        assert!(!content.contains("<math"));
+        assert!(images[name].starts_with("<svg xmlns"));
+    }
+}
@@ -0,0 +1,546 @@
+//! Content-hash fingerprinting of static assets.
+//!
+//! Once assets are optimized, every stylesheet, script and image is renamed
+//! to `name.<hash>.ext` and the references to it in the site's text files
+//! (pages, stylesheets, scripts, feeds, sitemaps and llms files) are
+//! rewritten, so browsers and gateways can cache them forever.
+//! `asset-manifest.json` maps each logical path to its fingerprinted one.
+//!
+//! Only URLs in reference contexts are rewritten: `src`, `href`, `srcset`
+//! and `content` attributes, CSS `url()`, string literals holding nothing
+//! but the URL, and markdown link destinations. An asset path mentioned in
+//! prose or in a code block is left as written.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tera::Value;
+use walkdir::WalkDir;
+
+use crate::optimization::STATIC_ASSET_DIRS;
+use crate::urls::BASE_URL;
+
+pub const MANIFEST_FILE: &str = "asset-manifest.json";
+/// Directories of the output whose files are fingerprinted.
+const ASSET_DIRS: &[&str] = &["styles", "js", "images"];
+/// Hex digits of the content hash in a fingerprinted name.
+const HASH_LENGTH: usize = 8;
+
+/// A path with an extension. In an absolute URL it starts at the `//` of
+/// the origin, which is stripped when the origin is the site's own.
+static ASSET_REF_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"/[\w./-]*\.[A-Za-z0-9]+").unwrap());
+/// A `src`, `href`, `srcset` or `content` attribute in a tag, and its value.
+static ATTRIBUTE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)(\b(?:src|href|srcset|content)\s*=\s*)("[^"]*"|'[^']*'|[^\s"'>]+)"#).unwrap()
+});
+/// The same attribute in HTML that was escaped into XML text.
+static ESCAPED_ATTRIBUTE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)(\b(?:src|href|srcset|content)=)(&quot;.*?&quot;)").unwrap());
+/// The same attribute in HTML inside a JSON string.
+static JSON_ATTRIBUTE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)(\b(?:src|href|srcset|content)=)(\\".*?\\")"#).unwrap());
+/// A script or style element with its content, or any other tag.
+static HTML_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)(<(script|style)\b[^>]*>)(.*?)(</(?:script|style)\s*>)|<[a-z][^>]*>").unwrap()
+});
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[A-Za-z][^>]*>").unwrap());
+static ESCAPED_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)&lt;[A-Za-z].*?&gt;").unwrap());
+static CSS_URL_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)(\burl\(\s*)("[^"]*"|'[^']*'|[^)"'\s]+)"#).unwrap());
+static JS_STRING_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#""(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*'|`(?:[^`\\]|\\.)*`"#).unwrap()
+});
+/// JSON Feed's `content_html`, the one JSON field holding markup, or any
+/// other JSON string.
+static JSON_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#""content_html"\s*:\s*"(?:[^"\\]|\\.)*"|"(?:[^"\\]|\\.)*""#).unwrap()
+});
+/// RSS content in a CDATA section, Atom content escaped into an element,
+/// any other tag, or element text.
+static XML_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)<!\[CDATA\[(.*?)\]\]>|(<content(?:\s[^>]*)?>)(.*?)(</content>)|<[A-Za-z][^>]*>|[^<]+",
+    )
+    .unwrap()
+});
+/// A fenced code block or code span, which is kept as written, or a link
+/// destination.
+static MARKDOWN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?m)^[ \t]*(?:```|~~~)[\s\S]*?^[ \t]*(?:```|~~~)|`[^`\n]+`|(\]\(\s*)(<[^>\n]*>|[^)\s]+)",
+    )
+    .unwrap()
+});
+
+/// `//host` of [`BASE_URL`].
+static SITE_ORIGIN: Lazy<&str> = Lazy::new(|| {
+    BASE_URL
+        .split_once(':')
+        .map_or(BASE_URL, |(_, origin)| origin)
+});
+
+/// Logical asset path → fingerprinted path, both root-relative.
+pub type AssetManifest = BTreeMap<String, String>;
+
+/// Syntax of a text file, which decides where it refers to assets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextKind {
+    Html,
+    Css,
+    Js,
+    Xml,
+    Json,
+    /// Markdown mirrors and llms files.
+    Markdown,
+}
+
+impl TextKind {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "html" => Some(Self::Html),
+            "css" => Some(Self::Css),
+            "js" => Some(Self::Js),
+            "xml" => Some(Self::Xml),
+            "json" => Some(Self::Json),
+            "md" | "txt" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Tera function resolving the logical path of a static asset.
+/// Usage in template: {{ asset_url(path="/js/prefetch.js") | safe }}
+/// Unknown assets fail the build; the returned path is replaced with the
+/// fingerprinted one after optimization.
+pub fn asset_url(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let path = args
+        .get("path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| tera::Error::msg("asset_url requires a 'path' argument"))?;
+    if source_path(path).is_none() {
+        return Err(tera::Error::msg(format!(
+            "asset_url: unknown asset '{}'",
+            path
+        )));
+    }
+    Ok(Value::String(path.to_string()))
+}
+
+/// Source file of the asset published at `path`.
+fn source_path(path: &str) -> Option<PathBuf> {
+    let (dir, name) = path.strip_prefix('/')?.split_once('/')?;
+    if name.split('/').any(|part| part.is_empty() || part == "..") {
+        return None;
+    }
+    let candidates: Vec<PathBuf> = match dir {
+        "images" => vec![Path::new("website/images").join(name)],
+        "styles" | "js" if !name.contains('/') => STATIC_ASSET_DIRS
+            .iter()
+            .map(|source_dir| Path::new("website").join(source_dir).join(name))
+            .collect(),
+        _ => return None,
+    };
+    let extension = |extension: &str| path.ends_with(&format!(".{}", extension));
+    let matches_dir = match dir {
+        "styles" => extension("css"),
+        "js" => extension("js"),
+        _ => true,
+    };
+    candidates
+        .into_iter()
+        .find(|candidate| matches_dir && candidate.is_file())
+}
+
+pub fn fingerprint_assets(out_dir: &Path) -> Result<AssetManifest> {
+    let start = Instant::now();
+
+    let mut assets = Vec::new();
+    for dir in ASSET_DIRS {
+        for entry in WalkDir::new(out_dir.join(dir)).sort_by_file_name() {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                assets.push(entry.into_path());
+            }
+        }
+    }
+    let logical = |path: &Path| -> Result<String> {
+        Ok(format!(
+            "/{}",
+            path.strip_prefix(out_dir)?
+                .to_string_lossy()
+                .replace('\\', "/")
+        ))
+    };
+    let (mut pending, images): (Vec<PathBuf>, Vec<PathBuf>) = assets
+        .into_iter()
+        .partition(|path| TextKind::of(path).is_some());
+
+    // Images reference nothing, so their hashes are final right away
+    let mut manifest: AssetManifest = images
+        .par_iter()
+        .map(|path| Ok((logical(path)?, fingerprint_file(path, out_dir)?)))
+        .collect::<Result<_>>()?;
+
+    // Stylesheets and scripts are hashed after their own references are
+    // rewritten, so an asset goes before whatever refers to it
+    let mut texts: HashMap<PathBuf, (TextKind, String)> = pending
+        .iter()
+        .filter_map(|path| Some((path, TextKind::of(path)?)))
+        .map(|(path, kind)| Ok((path.clone(), (kind, fs::read_to_string(path)?))))
+        .collect::<Result<_>>()?;
+    let pending_paths = |pending: &[PathBuf]| -> Result<Vec<String>> {
+        pending.iter().map(|path| logical(path)).collect()
+    };
+    loop {
+        let waiting = pending_paths(&pending)?;
+        let (ready, blocked): (Vec<PathBuf>, Vec<PathBuf>) =
+            pending.into_iter().partition(|path| {
+                let (kind, text) = &texts[path];
+                let own = logical(path).unwrap_or_default();
+                !references(text, *kind)
+                    .iter()
+                    .any(|reference| *reference != own && waiting.contains(reference))
+            });
+        pending = blocked;
+        if ready.is_empty() {
+            break;
+        }
+        for path in ready {
+            if let Some((kind, text)) = texts.remove(&path) {
+                fs::write(&path, rewrite_references(&text, kind, &manifest))?;
+            }
+            manifest.insert(logical(&path)?, fingerprint_file(&path, out_dir)?);
+        }
+    }
+    // Assets referring to each other in a cycle keep their names, so the
+    // references between them stay valid
+    for path in &pending {
+        let (kind, text) = &texts[path];
+        fs::write(path, rewrite_references(text, *kind, &manifest))?;
+    }
+
+    let fingerprinted: HashSet<&String> = manifest.values().collect();
+    let text_files: Vec<(PathBuf, TextKind)> = WalkDir::new(out_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| Some((TextKind::of(entry.path())?, entry.into_path())))
+        .map(|(kind, path)| (path, kind))
+        .filter(|(path, _)| {
+            !pending.contains(path)
+                && logical(path).is_ok_and(|logical| !fingerprinted.contains(&logical))
+        })
+        .collect();
+    let rewritten: usize = text_files
+        .par_iter()
+        .map(|(path, kind)| -> Result<usize> {
+            let text = fs::read_to_string(path)?;
+            let result = rewrite_references(&text, *kind, &manifest);
+            if result == text {
+                return Ok(0);
+            }
+            fs::write(path, result)?;
+            Ok(1)
+        })
+        .sum::<Result<usize>>()?;
+
+    fs::write(
+        out_dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    println!(
+        "✓ Fingerprinted {} assets and rewrote {} files in {:.2}s",
+        manifest.len(),
+        rewritten,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(manifest)
+}
+
+/// Renames the asset at `path` after its content hash and returns its new
+/// root-relative path.
+fn fingerprint_file(path: &Path, out_dir: &Path) -> Result<String> {
+    let hash = format!("{:x}", Sha256::digest(fs::read(path)?));
+    let name = fingerprinted_name(
+        &path.file_name().unwrap_or_default().to_string_lossy(),
+        &hash[..HASH_LENGTH],
+    );
+    let target = path.with_file_name(name);
+    fs::rename(path, &target)?;
+    Ok(format!(
+        "/{}",
+        target
+            .strip_prefix(out_dir)?
+            .to_string_lossy()
+            .replace('\\', "/")
+    ))
+}
+
+/// `global.css` → `global.<hash>.css`.
+fn fingerprinted_name(name: &str, hash: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}.{}.{}", stem, hash, extension),
+        _ => format!("{}.{}", name, hash),
+    }
+}
+
+/// A path found by [`ASSET_REF_RE`] split into the site's origin, if it
+/// has it, and the root-relative path.
+fn split_origin(reference: &str) -> (&str, &str) {
+    match reference.strip_prefix(*SITE_ORIGIN) {
+        Some(path) if path.starts_with('/') => reference.split_at(SITE_ORIGIN.len()),
+        _ => ("", reference),
+    }
+}
+
+/// Root-relative paths referenced in `text`.
+fn references(text: &str, kind: TextKind) -> Vec<String> {
+    let mut references = Vec::new();
+    map_references(text, kind, &mut |path| {
+        references.push(path.to_string());
+        None
+    });
+    references
+}
+
+/// `text` with every reference to an asset of `manifest` pointing at its
+/// fingerprinted path, on the site's origin if it was absolute.
+fn rewrite_references(text: &str, kind: TextKind, manifest: &AssetManifest) -> String {
+    map_references(text, kind, &mut |path| manifest.get(path).cloned())
+}
+
+/// Replacement of a root-relative asset path, or `None` to keep it.
+type MapPath<'a> = dyn FnMut(&str) -> Option<String> + 'a;
+
+/// `text` with the paths in its reference contexts passed through `map`.
+fn map_references(text: &str, kind: TextKind, map: &mut MapPath) -> String {
+    match kind {
+        TextKind::Html => map_html(text, map),
+        TextKind::Css => replace_value(text, &CSS_URL_RE, map),
+        TextKind::Js => map_strings(text, &JS_STRING_RE, map),
+        TextKind::Json => JSON_RE
+            .replace_all(text, |caps: &Captures| {
+                if caps[0].starts_with("\"content_html\"") {
+                    map_tags(&caps[0], &TAG_RE, &JSON_ATTRIBUTE_RE, map)
+                } else {
+                    map_string(&caps[0], map)
+                }
+            })
+            .into_owned(),
+        TextKind::Xml => XML_RE
+            .replace_all(text, |caps: &Captures| {
+                if let Some(content) = caps.get(1) {
+                    format!("<![CDATA[{}]]>", map_html(content.as_str(), map))
+                } else if let Some(content) = caps.get(3) {
+                    let content = map_tags(
+                        content.as_str(),
+                        &ESCAPED_TAG_RE,
+                        &ESCAPED_ATTRIBUTE_RE,
+                        map,
+                    );
+                    format!("{}{}{}", &caps[2], content, &caps[4])
+                } else if caps[0].starts_with('<') {
+                    replace_value(&caps[0], &ATTRIBUTE_RE, map)
+                } else if is_url(caps[0].trim()) {
+                    map_urls(&caps[0], map)
+                } else {
+                    caps[0].to_string()
+                }
+            })
+            .into_owned(),
+        TextKind::Markdown => MARKDOWN_RE
+            .replace_all(text, |caps: &Captures| match (caps.get(1), caps.get(2)) {
+                (Some(prefix), Some(destination)) => {
+                    format!("{}{}", prefix.as_str(), map_urls(destination.as_str(), map))
+                }
+                _ => caps[0].to_string(),
+            })
+            .into_owned(),
+    }
+}
+
+/// Tag attributes of HTML, and URLs in the CSS and scripts it embeds.
+fn map_html(html: &str, map: &mut MapPath) -> String {
+    HTML_RE
+        .replace_all(html, |caps: &Captures| {
+            let Some(content) = caps.get(3) else {
+                return replace_value(&caps[0], &ATTRIBUTE_RE, map);
+            };
+            let content = if caps[2].eq_ignore_ascii_case("style") {
+                replace_value(content.as_str(), &CSS_URL_RE, map)
+            } else {
+                map_strings(content.as_str(), &JS_STRING_RE, map)
+            };
+            format!(
+                "{}{}{}",
+                replace_value(&caps[1], &ATTRIBUTE_RE, map),
+                content,
+                &caps[4]
+            )
+        })
+        .into_owned()
+}
+
+/// Values of the attributes `attribute_re` finds in the tags `tag_re` finds.
+fn map_tags(text: &str, tag_re: &Regex, attribute_re: &Regex, map: &mut MapPath) -> String {
+    tag_re
+        .replace_all(text, |caps: &Captures| {
+            replace_value(&caps[0], attribute_re, map)
+        })
+        .into_owned()
+}
+
+/// Group 2 of each match of `re`, a value following the context in group 1.
+fn replace_value(text: &str, re: &Regex, map: &mut MapPath) -> String {
+    re.replace_all(text, |caps: &Captures| {
+        format!("{}{}", &caps[1], map_urls(&caps[2], map))
+    })
+    .into_owned()
+}
+
+/// String literals that hold nothing but a URL.
+fn map_strings(text: &str, re: &Regex, map: &mut MapPath) -> String {
+    re.replace_all(text, |caps: &Captures| map_string(&caps[0], map))
+        .into_owned()
+}
+
+fn map_string(literal: &str, map: &mut MapPath) -> String {
+    match literal.get(1..literal.len().saturating_sub(1)) {
+        Some(content) if is_url(content) => map_urls(literal, map),
+        _ => literal.to_string(),
+    }
+}
+
+/// Whether `text` is a single root-relative or absolute URL of a file.
+fn is_url(text: &str) -> bool {
+    ASSET_REF_RE.find(text).is_some_and(|found| {
+        let scheme = &text[..found.start()];
+        found.end() == text.len() && (scheme.is_empty() || scheme == "https:" || scheme == "http:")
+    })
+}
+
+/// `value`, a URL or a `srcset` list of them, with each path on the site
+/// passed through `map`.
+fn map_urls(value: &str, map: &mut MapPath) -> String {
+    ASSET_REF_RE
+        .replace_all(value, |caps: &Captures| {
+            let (origin, path) = split_origin(&caps[0]);
+            match map(path) {
+                Some(mapped) => format!("{}{}", origin, mapped),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprinted_name, rewrite_references, AssetManifest, TextKind};
+
+    #[test]
+    fn names_keep_their_extension_last() {
+        assert_eq!(
+            fingerprinted_name("global.css", "1a2b3c4d"),
+            "global.1a2b3c4d.css"
+        );
+        assert_eq!(
+            fingerprinted_name("plot-480w.png", "1a2b3c4d"),
+            "plot-480w.1a2b3c4d.png"
+        );
+    }
+
+    fn manifest() -> AssetManifest {
+        AssetManifest::from([
+            (
+                "/images/plot.png".to_string(),
+                "/images/plot.1a2b3c4d.png".to_string(),
+            ),
+            (
+                "/js/search.js".to_string(),
+                "/js/search.5e6f7a8b.js".to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn page_references_are_rewritten_but_code_and_prose_are_not() {
+        let html = concat!(
+            r#"<img src=/images/plot.png sizes="100vw"srcset="/images/plot.png 900w, https://seanpedersen.github.io/images/plot.png 2x">"#,
+            r#"<meta content="https://example.com/images/plot.png"><a href="/images/plot.png.bak">x</a>"#,
+            "<script>script.src = '/js/search.js'; const doc = 'see /js/search.js';</script>",
+            "<style>.a{background:url(/images/plot.png)}</style>",
+            "<p>The plot lives at /images/plot.png.</p>",
+            "<pre><code>&lt;img src=&quot;/images/plot.png&quot;&gt; url(/images/plot.png) '/js/search.js'</code></pre>",
+        );
+
+        assert_eq!(
+            rewrite_references(html, TextKind::Html, &manifest()),
+            concat!(
+                r#"<img src=/images/plot.1a2b3c4d.png sizes="100vw"srcset="/images/plot.1a2b3c4d.png 900w, https://seanpedersen.github.io/images/plot.1a2b3c4d.png 2x">"#,
+                r#"<meta content="https://example.com/images/plot.png"><a href="/images/plot.png.bak">x</a>"#,
+                "<script>script.src = '/js/search.5e6f7a8b.js'; const doc = 'see /js/search.js';</script>",
+                "<style>.a{background:url(/images/plot.1a2b3c4d.png)}</style>",
+                "<p>The plot lives at /images/plot.png.</p>",
+                "<pre><code>&lt;img src=&quot;/images/plot.png&quot;&gt; url(/images/plot.png) '/js/search.js'</code></pre>",
+            )
+        );
+    }
+
+    #[test]
+    fn markdown_links_are_rewritten_outside_code() {
+        let markdown = concat!(
+            "![Plot](https://seanpedersen.github.io/images/plot.png) and `/images/plot.png`\n",
+            "```html\n<img src=\"/images/plot.png\">\n![Plot](/images/plot.png)\n```\n",
+        );
+
+        assert_eq!(
+            rewrite_references(markdown, TextKind::Markdown, &manifest()),
+            concat!(
+                "![Plot](https://seanpedersen.github.io/images/plot.1a2b3c4d.png) and `/images/plot.png`\n",
+                "```html\n<img src=\"/images/plot.png\">\n![Plot](/images/plot.png)\n```\n",
+            )
+        );
+    }
+
+    #[test]
+    fn feed_references_are_rewritten_in_their_markup() {
+        let xml = concat!(
+            "<image:loc>https://seanpedersen.github.io/images/plot.png</image:loc>",
+            "<description>Drawn as /images/plot.png</description>",
+            r#"<content:encoded><![CDATA[<img src="/images/plot.png"> &lt;img src=&quot;/images/plot.png&quot;&gt;]]></content:encoded>"#,
+            r#"<content type="html">&lt;img alt=&quot;&quot;"#,
+            "\n",
+            r#"  src=&quot;/images/plot.png&quot;&gt;&amp;lt;img src=&amp;quot;/images/plot.png&amp;quot;&amp;gt;</content>"#,
+        );
+        let json = concat!(
+            r#"{"image": "/images/plot.png", "summary": "Drawn as /images/plot.png", "#,
+            r#""content_html": "<img src=\"/images/plot.png\"> &lt;img src=&quot;/images/plot.png&quot;&gt;"}"#,
+        );
+
+        assert_eq!(
+            rewrite_references(xml, TextKind::Xml, &manifest()),
+            concat!(
+                "<image:loc>https://seanpedersen.github.io/images/plot.1a2b3c4d.png</image:loc>",
+                "<description>Drawn as /images/plot.png</description>",
+                r#"<content:encoded><![CDATA[<img src="/images/plot.1a2b3c4d.png"> &lt;img src=&quot;/images/plot.png&quot;&gt;]]></content:encoded>"#,
+                r#"<content type="html">&lt;img alt=&quot;&quot;"#,
+                "\n",
+                r#"  src=&quot;/images/plot.1a2b3c4d.png&quot;&gt;&amp;lt;img src=&amp;quot;/images/plot.png&amp;quot;&amp;gt;</content>"#,
+            )
+        );
+        assert_eq!(
+            rewrite_references(json, TextKind::Json, &manifest()),
+            concat!(
+                r#"{"image": "/images/plot.1a2b3c4d.png", "summary": "Drawn as /images/plot.png", "#,
+                r#""content_html": "<img src=\"/images/plot.1a2b3c4d.png\"> &lt;img src=&quot;/images/plot.png&quot;&gt;"}"#,
+            )
+        );
+    }
+}
@@ -4,20 +4,14 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::time::Instant;
-use tera::{Tera, Value};
+use tera::Tera;
 
-/// Tera function that outputs a placeholder for CSS inlining.
-/// Usage in template: {{ inline_css(path="/styles/global.css") }}
-/// The placeholder is replaced with actual CSS content during optimization.
-fn inline_css_placeholder(args: &std::collections::HashMap<String, Value>) -> tera::Result<Value> {
-    let path = args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| tera::Error::msg("inline_css requires a 'path' argument"))?;
-    Ok(Value::String(format!("<!-- INLINE_CSS:{} -->", path)))
-}
+use crate::fingerprinting;
+use crate::optimization;
+use crate::reproducible::build_time;
+use crate::urls::SiteUrls;
 
-pub fn build_global_html_pages(out_dir: &Path) -> Result<()> {
+pub fn build_global_html_pages(out_dir: &Path, urls: &SiteUrls) -> Result<()> {
     let start = Instant::now();
 
     let global_dir = Path::new("website/global");
@@ -49,9 +43,11 @@ pub fn build_global_html_pages(out_dir: &Path) -> Result<()> {
             Some(html_path.file_name().unwrap().to_str().unwrap()),
         )?;
     }
-    tera.register_function("inline_css", inline_css_placeholder);
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
 
-    let year = chrono::Local::now().year();
+    let year = build_time().year();
 
     let mut context = tera::Context::new();
     context.insert("year", &year);
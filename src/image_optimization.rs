@@ -0,0 +1,844 @@
+//! Optimizes the site's raster images in place.
+//!
+//! Every PNG, JPEG and WebP loses its metadata (EXIF, including GPS
+//! positions, XMP, IPTC and text chunks), and images wider than
+//! [`MAX_IMAGE_WIDTH`] are scaled down. PNG and JPEG images also get `.webp`
+//! siblings (`plot.png` → `plot.png.webp`; lossless, or lossy for JPEG photos
+//! with the `lossy-webp` feature) and, with the `avif` feature, `.avif` ones,
+//! which pages offer through `<picture>` sources while feeds and social
+//! previews keep the original.
+//! Images wider than one of [`RESPONSIVE_WIDTHS`] also get narrower copies
+//! (`plot.png` → `plot-480w.png`, with their own siblings) for `srcset`.
+//! A re-encoding is only kept if it is smaller than what it would replace.
+//!
+//! Encoding is slow, so results are cached in `images/` of the cache
+//! directory under a hash of the source image and the pipeline settings.
+
+use anyhow::{bail, Context, Result};
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, ImageResult};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Images wider than this are scaled down: twice the width of the content
+/// column, for high-density screens.
+pub const MAX_IMAGE_WIDTH: u32 = 1600;
+/// Widths of the narrower copies offered through `srcset`.
+pub const RESPONSIVE_WIDTHS: &[u32] = &[480, 800, 1200];
+/// Where the images served at `/images/` come from.
+const SOURCE_IMAGES_DIR: &str = "website/images";
+const JPEG_QUALITY: u8 = 85;
+/// Quality of the lossy WebP siblings of JPEG images.
+#[cfg(feature = "lossy-webp")]
+const WEBP_QUALITY: f32 = 80.0;
+#[cfg(feature = "avif")]
+const AVIF_QUALITY: u8 = 70;
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+/// Cache of encoded images, relative to the cache directory.
+const CACHE_SUBDIR: &str = "images";
+/// Written last into a cache entry, so interrupted builds leave no partial entries.
+const CACHE_COMPLETE_MARKER: &str = "complete";
+/// Changed whenever the pipeline produces different output for the same
+/// source, so stale cache entries are not reused.
+const PIPELINE_VERSION: u32 = 3;
+
+/// Source formats that get modern-format siblings.
+const SIBLING_SOURCES: &[&str] = &["png", "jpg", "jpeg"];
+
+static IMG_OR_PICTURE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<picture\b.*?</picture\s*>|<img\b[^>]*>").unwrap());
+static SRC_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)\bsrc\s*=\s*"([^"]*)""#).unwrap());
+static SRCSET_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\bsrcset\s*=\s*"([^"]*)""#).unwrap());
+static SIZES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)\bsizes\s*=\s*"([^"]*)""#).unwrap());
+
+/// Outcome of [`optimize_images`].
+#[derive(Debug, Default)]
+pub struct ImageReport {
+    pub optimized: usize,
+    pub cached: usize,
+    pub failed: usize,
+    pub webp: usize,
+    pub avif: usize,
+    /// Narrower copies written for `srcset`.
+    pub variants: usize,
+}
+
+/// One published size of an image and the siblings worth keeping.
+struct Version {
+    /// Width of a narrower copy, `None` for the image itself.
+    width: Option<u32>,
+    image: Vec<u8>,
+    webp: Option<Vec<u8>>,
+    avif: Option<Vec<u8>>,
+}
+
+/// Dimensions of an image as published, and its `srcset` if it has
+/// narrower copies.
+#[derive(Debug, PartialEq)]
+pub struct ResponsiveImage {
+    pub width: u32,
+    pub height: u32,
+    pub srcset: Option<String>,
+}
+
+/// Optimizes every image in `paths` in place and writes its siblings next
+/// to it. Images that cannot be decoded are left as they are.
+pub fn optimize_images(paths: &[PathBuf], cache_dir: &Path) -> ImageReport {
+    let cache_dir = cache_dir.join(CACHE_SUBDIR);
+    let sources: HashSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    let results: Vec<Result<OptimizedFile>> = paths
+        .par_iter()
+        .map(|path| {
+            let result = optimize_image_file(path, &cache_dir, &sources);
+            if let Err(e) = &result {
+                eprintln!("Warning: Could not optimize {}: {:#}", path.display(), e);
+            }
+            result
+        })
+        .collect();
+
+    let mut report = ImageReport::default();
+    let mut used = HashSet::new();
+    for file in results.into_iter().flatten() {
+        used.insert(file.key);
+        report.optimized += 1;
+        report.cached += file.cached as usize;
+        report.webp += file.webp;
+        report.avif += file.avif;
+        report.variants += file.variants;
+    }
+    report.failed = paths.len() - report.optimized;
+
+    // Drop entries of images that changed or no longer exist
+    if let Ok(entries) = fs::read_dir(&cache_dir) {
+        for entry in entries.flatten() {
+            if !used.contains(entry.file_name().to_string_lossy().as_ref()) {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+    }
+    report
+}
+
+/// What [`optimize_image_file`] did with one image.
+struct OptimizedFile {
+    /// Name of the image's cache entry.
+    key: String,
+    cached: bool,
+    webp: usize,
+    avif: usize,
+    variants: usize,
+}
+
+/// Optimizes the image at `path`. Siblings are not written over any of
+/// `sources`, the images being optimized.
+fn optimize_image_file(
+    path: &Path,
+    cache_dir: &Path,
+    sources: &HashSet<&Path>,
+) -> Result<OptimizedFile> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let source = fs::read(path)?;
+    let key = cache_key(&source, &extension);
+    let entry = cache_dir.join(&key);
+
+    let (cached, versions) = match read_cache_entry(&entry, &extension) {
+        Some(versions) => (true, versions),
+        None => {
+            let versions = optimize(&source, &extension)?;
+            write_cache_entry(&entry, &extension, &versions)?;
+            (false, versions)
+        }
+    };
+
+    let mut file = OptimizedFile {
+        key,
+        cached,
+        webp: 0,
+        avif: 0,
+        variants: 0,
+    };
+    let write = |target: &Path, bytes: &Option<Vec<u8>>| -> Result<bool> {
+        match bytes {
+            Some(bytes) if !sources.contains(target) => {
+                fs::write(target, bytes)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    };
+    for version in versions {
+        let target = match version.width {
+            Some(width) => variant_path(path, width),
+            None => path.to_path_buf(),
+        };
+        if version.width.is_none() {
+            fs::write(path, &version.image)?;
+        } else if write(&target, &Some(version.image))? {
+            file.variants += 1;
+        } else {
+            continue;
+        }
+        file.webp += write(&sibling_path(&target, "webp"), &version.webp)? as usize;
+        file.avif += write(&sibling_path(&target, "avif"), &version.avif)? as usize;
+    }
+    Ok(file)
+}
+
+/// Path of the copy of the image at `path` scaled to `width`.
+fn variant_path(path: &Path, width: u32) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(variant_name(&name, width))
+}
+
+/// `plot.png` → `plot.png.webp`. The source extension stays in the name, so
+/// `plot.png` and `plot.jpg` do not share a sibling.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// `plot.png` → `plot-480w.png`; also works on URLs.
+pub fn variant_name(name: &str, width: u32) -> String {
+    match name.rfind('.').filter(|&dot| !name[dot..].contains('/')) {
+        Some(dot) => format!("{}-{}w{}", &name[..dot], width, &name[dot..]),
+        None => format!("{}-{}w", name, width),
+    }
+}
+
+fn cache_key(source: &[u8], extension: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(
+        format!(
+            "{}:{}:{}:{}:{}",
+            PIPELINE_VERSION,
+            MAX_IMAGE_WIDTH,
+            cfg!(feature = "avif"),
+            cfg!(feature = "lossy-webp"),
+            extension
+        )
+        .as_bytes(),
+    );
+    hasher.update(source);
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
+
+/// Name of a version's file in a cache entry.
+fn cache_file_name(width: Option<u32>, extension: &str) -> String {
+    match width {
+        Some(width) => format!("image-{}w.{}", width, extension),
+        None => format!("image.{}", extension),
+    }
+}
+
+fn read_cache_entry(entry: &Path, extension: &str) -> Option<Vec<Version>> {
+    if !entry.join(CACHE_COMPLETE_MARKER).is_file() {
+        return None;
+    }
+    let read = |width: Option<u32>, extension: &str| {
+        fs::read(entry.join(cache_file_name(width, extension))).ok()
+    };
+    let siblings = SIBLING_SOURCES.contains(&extension);
+    let mut versions = Vec::new();
+    for width in std::iter::once(None).chain(RESPONSIVE_WIDTHS.iter().copied().map(Some)) {
+        let Some(image) = read(width, extension) else {
+            // Narrower copies are optional, the image itself is not
+            width?;
+            continue;
+        };
+        versions.push(Version {
+            width,
+            image,
+            webp: read(width, "webp").filter(|_| siblings),
+            avif: read(width, "avif").filter(|_| siblings),
+        });
+    }
+    Some(versions)
+}
+
+fn write_cache_entry(entry: &Path, extension: &str, versions: &[Version]) -> Result<()> {
+    fs::create_dir_all(entry)?;
+    for version in versions {
+        let write = |extension: &str, bytes: &[u8]| {
+            fs::write(entry.join(cache_file_name(version.width, extension)), bytes)
+        };
+        write(extension, &version.image)?;
+        if let Some(webp) = &version.webp {
+            write("webp", webp)?;
+        }
+        if let Some(avif) = &version.avif {
+            write("avif", avif)?;
+        }
+    }
+    fs::write(entry.join(CACHE_COMPLETE_MARKER), "")?;
+    Ok(())
+}
+
+/// The image format of a file extension the pipeline handles.
+fn image_format(extension: &str) -> Option<ImageFormat> {
+    match extension {
+        "png" => Some(ImageFormat::Png),
+        "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+        "webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Height of a `width` × `height` image scaled to `target_width`.
+fn scaled_height(width: u32, height: u32, target_width: u32) -> u32 {
+    (height as u64 * target_width as u64 / width as u64).max(1) as u32
+}
+
+/// The optimized image, followed by its narrower copies.
+fn optimize(source: &[u8], extension: &str) -> Result<Vec<Version>> {
+    let Some(format) = image_format(extension) else {
+        bail!("Unsupported image format '{}'", extension);
+    };
+    let stripped = strip_metadata(source, format).context("Malformed image")?;
+
+    let mut decoder = ImageReader::with_format(Cursor::new(source), format).into_decoder()?;
+    let orientation = image::ImageDecoder::orientation(&mut decoder)?;
+    let mut decoded = DynamicImage::from_decoder(decoder)?;
+
+    // Stripping EXIF drops the orientation, so it has to be applied to the pixels
+    let rotated = orientation != image::metadata::Orientation::NoTransforms;
+    decoded.apply_orientation(orientation);
+    let resized = decoded.width() > MAX_IMAGE_WIDTH;
+    if resized {
+        let height = scaled_height(decoded.width(), decoded.height(), MAX_IMAGE_WIDTH);
+        decoded = decoded.resize_exact(MAX_IMAGE_WIDTH, height, FilterType::Lanczos3);
+    }
+
+    let image = if rotated || resized {
+        encode(&decoded, format)?
+    } else if format == ImageFormat::Png {
+        // Recompression only helps PNGs saved without maximum compression
+        smallest(stripped, Some(encode(&decoded, format)?))
+    } else {
+        stripped
+    };
+
+    let mut versions = vec![with_siblings(None, image, &decoded, extension)?];
+    for &width in RESPONSIVE_WIDTHS {
+        if width >= decoded.width() {
+            continue;
+        }
+        let height = scaled_height(decoded.width(), decoded.height(), width);
+        let scaled = decoded.resize_exact(width, height, FilterType::Lanczos3);
+        let image = encode(&scaled, format)?;
+        versions.push(with_siblings(Some(width), image, &scaled, extension)?);
+    }
+    Ok(versions)
+}
+
+/// `image` with the siblings of `decoded` that are smaller than it.
+fn with_siblings(
+    width: Option<u32>,
+    image: Vec<u8>,
+    decoded: &DynamicImage,
+    extension: &str,
+) -> Result<Version> {
+    let (webp, avif) = if SIBLING_SOURCES.contains(&extension) {
+        let webp = if extension == "png" {
+            encode_webp(decoded)?
+        } else {
+            encode_photo_webp(decoded)?
+        };
+        (
+            Some(webp).filter(|webp| webp.len() < image.len()),
+            encode_avif(decoded)?.filter(|avif| avif.len() < image.len()),
+        )
+    } else {
+        (None, None)
+    };
+    Ok(Version {
+        width,
+        image,
+        webp,
+        avif,
+    })
+}
+
+fn smallest(image: Vec<u8>, candidate: Option<Vec<u8>>) -> Vec<u8> {
+    match candidate {
+        Some(candidate) if candidate.len() < image.len() => candidate,
+        _ => image,
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new_with_quality(
+            &mut out,
+            CompressionType::Best,
+            PngFilter::Adaptive,
+        ))?,
+        ImageFormat::Jpeg => image.to_rgb8().write_with_encoder(
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY),
+        )?,
+        _ => return encode_webp(image),
+    }
+    Ok(out)
+}
+
+/// Lossless WebP, the only kind the pure Rust encoder writes.
+fn encode_webp(image: &DynamicImage) -> ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    let encoder = image::codecs::webp::WebPEncoder::new_lossless(&mut out);
+    if image.color().has_alpha() {
+        image.to_rgba8().write_with_encoder(encoder)?;
+    } else {
+        image.to_rgb8().write_with_encoder(encoder)?;
+    }
+    Ok(out)
+}
+
+/// Lossy WebP through libwebp: photos compress far better lossy.
+#[cfg(feature = "lossy-webp")]
+fn encode_photo_webp(image: &DynamicImage) -> Result<Vec<u8>> {
+    let rgb = image.to_rgb8();
+    let encoder = webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height());
+    let webp = encoder
+        .encode_simple(false, WEBP_QUALITY)
+        .map_err(|e| anyhow::anyhow!("WebP encode error: {:?}", e))?;
+    Ok(webp.to_vec())
+}
+
+/// Lossless WebP, which is only kept in the rare case it beats the JPEG.
+#[cfg(not(feature = "lossy-webp"))]
+fn encode_photo_webp(image: &DynamicImage) -> Result<Vec<u8>> {
+    Ok(encode_webp(image)?)
+}
+
+#[cfg(feature = "avif")]
+fn encode_avif(image: &DynamicImage) -> ImageResult<Option<Vec<u8>>> {
+    let mut out = Vec::new();
+    let encoder = image::codecs::avif::AvifEncoder::new_with_speed_quality(
+        &mut out,
+        AVIF_SPEED,
+        AVIF_QUALITY,
+    );
+    image.to_rgba8().write_with_encoder(encoder)?;
+    Ok(Some(out))
+}
+
+#[cfg(not(feature = "avif"))]
+fn encode_avif(_image: &DynamicImage) -> ImageResult<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+/// `source` without metadata, leaving the image data untouched. `None` if
+/// the container is malformed.
+fn strip_metadata(source: &[u8], format: ImageFormat) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Png => strip_png_metadata(source),
+        ImageFormat::Jpeg => strip_jpeg_metadata(source),
+        ImageFormat::WebP => strip_webp_metadata(source),
+        _ => Some(source.to_vec()),
+    }
+}
+
+fn strip_png_metadata(source: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const METADATA_CHUNKS: &[&[u8]] = &[b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+    let mut out = source.get(..SIGNATURE.len())?.to_vec();
+    if out != SIGNATURE {
+        return None;
+    }
+    let mut pos = SIGNATURE.len();
+    while pos < source.len() {
+        let length = u32::from_be_bytes(source.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        // Length, type, data and CRC
+        let end = pos.checked_add(12 + length)?;
+        let chunk = source.get(pos..end)?;
+        if !METADATA_CHUNKS.contains(&&chunk[4..8]) {
+            out.extend_from_slice(chunk);
+        }
+        pos = end;
+    }
+    Some(out)
+}
+
+fn strip_jpeg_metadata(source: &[u8]) -> Option<Vec<u8>> {
+    const APP1: u8 = 0xE1; // EXIF and XMP
+    const APP13: u8 = 0xED; // IPTC
+    const COM: u8 = 0xFE;
+    const SOS: u8 = 0xDA;
+
+    if source.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut out = vec![0xFF, 0xD8];
+    let mut pos = 2;
+    loop {
+        if *source.get(pos)? != 0xFF {
+            return None;
+        }
+        // Markers may be preceded by any number of fill bytes
+        while *source.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        let marker = source[pos + 1];
+        let length = u16::from_be_bytes([*source.get(pos + 2)?, *source.get(pos + 3)?]) as usize;
+        let end = pos + 2 + length;
+        let segment = source.get(pos..end)?;
+        if marker == SOS {
+            // Entropy-coded data follows; copy the rest as it is
+            out.extend_from_slice(&source[pos..]);
+            return Some(out);
+        }
+        if !matches!(marker, APP1 | APP13 | COM) {
+            out.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+}
+
+fn strip_webp_metadata(source: &[u8]) -> Option<Vec<u8>> {
+    const VP8X_EXIF_FLAG: u8 = 0x08;
+    const VP8X_XMP_FLAG: u8 = 0x04;
+
+    if source.get(..4)? != b"RIFF" || source.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut out = source[..12].to_vec();
+    let mut pos = 12;
+    while pos < source.len() {
+        let fourcc = source.get(pos..pos + 4)?;
+        let size = u32::from_le_bytes(source.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // Chunks are padded to an even size
+        let end = (pos + 8 + size + (size & 1)).min(source.len());
+        let chunk = source.get(pos..end)?;
+        match fourcc {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let mut chunk = chunk.to_vec();
+                *chunk.get_mut(8)? &= !(VP8X_EXIF_FLAG | VP8X_XMP_FLAG);
+                out.extend_from_slice(&chunk);
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        pos = end;
+    }
+    let riff_size = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+/// Size and `srcset` of the image at `url` once optimized, read from its
+/// source in `website/images/`. `None` for images served from elsewhere or
+/// that cannot be read.
+pub fn responsive_image(url: &str) -> Option<ResponsiveImage> {
+    let relative = url.strip_prefix("/images/")?;
+    if relative.contains(['?', '#']) || relative.split('/').any(|part| part == "..") {
+        return None;
+    }
+    let path = Path::new(SOURCE_IMAGES_DIR).join(relative);
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    if extension == "svg" {
+        let tree =
+            resvg::usvg::Tree::from_data(&fs::read(&path).ok()?, &Default::default()).ok()?;
+        return Some(ResponsiveImage {
+            width: tree.size().width().round() as u32,
+            height: tree.size().height().round() as u32,
+            srcset: None,
+        });
+    }
+
+    let file = std::io::BufReader::new(fs::File::open(&path).ok()?);
+    let mut decoder = ImageReader::with_format(file, image_format(&extension)?)
+        .into_decoder()
+        .ok()?;
+    let (mut width, mut height) = image::ImageDecoder::dimensions(&decoder);
+    use image::metadata::Orientation::*;
+    if matches!(
+        image::ImageDecoder::orientation(&mut decoder).ok()?,
+        Rotate90 | Rotate270 | Rotate90FlipH | Rotate270FlipH
+    ) {
+        (width, height) = (height, width);
+    }
+    if width > MAX_IMAGE_WIDTH {
+        height = scaled_height(width, height, MAX_IMAGE_WIDTH);
+        width = MAX_IMAGE_WIDTH;
+    }
+
+    let variants: Vec<String> = RESPONSIVE_WIDTHS
+        .iter()
+        .filter(|&&variant| variant < width)
+        .map(|&variant| format!("{} {}w", variant_name(url, variant), variant))
+        .collect();
+    let srcset =
+        (!variants.is_empty()).then(|| format!("{}, {} {}w", variants.join(", "), url, width));
+    Some(ResponsiveImage {
+        width,
+        height,
+        srcset,
+    })
+}
+
+/// Local path in `out_dir` of a root-relative URL, if it has one.
+fn local_path(url: &str) -> Option<&Path> {
+    url.strip_prefix('/')
+        .filter(|_| !url.starts_with("//"))
+        .map(Path::new)
+}
+
+/// Wraps every `<img>` of a local image that has `.avif` or `.webp`
+/// siblings in `out_dir` in a `<picture>` offering them, along with the
+/// siblings of the narrower copies in its `srcset`.
+pub fn add_picture_sources(html: &str, out_dir: &Path) -> String {
+    IMG_OR_PICTURE_RE
+        .replace_all(html, |caps: &Captures| {
+            let img = &caps[0];
+            if !img[..4].eq_ignore_ascii_case("<img") {
+                return img.to_string();
+            }
+            let Some(src) = SRC_RE.captures(img).map(|src| src[1].to_string()) else {
+                return img.to_string();
+            };
+            let Some(path) = local_path(&src) else {
+                return img.to_string();
+            };
+            let is_sibling_source = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SIBLING_SOURCES.contains(&e.to_ascii_lowercase().as_str()));
+            if !is_sibling_source {
+                return img.to_string();
+            }
+
+            let srcset = SRCSET_RE.captures(img).map(|caps| caps[1].to_string());
+            let sizes = SIZES_RE
+                .captures(img)
+                .map(|caps| format!(r#" sizes="{}""#, &caps[1]))
+                .unwrap_or_default();
+            // A source offers the sibling of each candidate that has one, and
+            // the candidate itself otherwise
+            let candidates: Vec<(&str, &str)> = match &srcset {
+                Some(srcset) => srcset
+                    .split(',')
+                    .filter_map(|candidate| candidate.trim().split_once(' '))
+                    .collect(),
+                None => vec![(src.as_str(), "")],
+            };
+            let sources: String = [("avif", "image/avif"), ("webp", "image/webp")]
+                .iter()
+                .filter_map(|(extension, mime)| {
+                    let mut found = false;
+                    let candidates: Vec<String> = candidates
+                        .iter()
+                        .map(|&(url, descriptor)| {
+                            let sibling = local_path(url)
+                                .map(|path| sibling_path(path, extension))
+                                .filter(|sibling| out_dir.join(sibling).is_file());
+                            found |= sibling.is_some();
+                            let url = sibling.map_or(url.to_string(), |sibling| {
+                                format!("/{}", sibling.to_string_lossy())
+                            });
+                            format!("{} {}", url, descriptor).trim_end().to_string()
+                        })
+                        .collect();
+                    found.then(|| {
+                        format!(
+                            r#"<source type="{}" srcset="{}"{}>"#,
+                            mime,
+                            candidates.join(", "),
+                            sizes
+                        )
+                    })
+                })
+                .collect();
+            if sources.is_empty() {
+                img.to_string()
+            } else {
+                format!("<picture>{}{}</picture>", sources, img)
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_picture_sources, encode, optimize_images, strip_jpeg_metadata, strip_png_metadata,
+        strip_webp_metadata, variant_name,
+    };
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::fs;
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    #[test]
+    fn png_text_and_exif_chunks_are_dropped() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        png.extend(&ihdr);
+        png.extend(png_chunk(b"eXIf", b"GPS"));
+        png.extend(png_chunk(b"tEXt", b"Author\0me"));
+        png.extend(png_chunk(b"IEND", b""));
+
+        let stripped = strip_png_metadata(&png).unwrap();
+
+        let mut expected = b"\x89PNG\r\n\x1a\n".to_vec();
+        expected.extend(ihdr);
+        expected.extend(png_chunk(b"IEND", b""));
+        assert_eq!(stripped, expected);
+    }
+
+    #[test]
+    fn jpeg_exif_and_comments_are_dropped_before_scan_data() {
+        let jpeg = [
+            &[0xFF, 0xD8][..],
+            &[0xFF, 0xE0, 0x00, 0x04, b'J', b'F'],
+            &[0xFF, 0xE1, 0x00, 0x05, b'E', b'x', b'i'],
+            &[0xFF, 0xFE, 0x00, 0x03, b'c'],
+            &[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xE1, 0x12],
+            &[0xFF, 0xD9],
+        ]
+        .concat();
+
+        assert_eq!(
+            strip_jpeg_metadata(&jpeg).unwrap(),
+            [
+                &[0xFF, 0xD8][..],
+                &[0xFF, 0xE0, 0x00, 0x04, b'J', b'F'],
+                &[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xE1, 0x12],
+                &[0xFF, 0xD9],
+            ]
+            .concat()
+        );
+        assert_eq!(strip_jpeg_metadata(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn webp_metadata_chunks_and_flags_are_dropped() {
+        let webp = [
+            &b"RIFF"[..],
+            &[0; 4],
+            b"WEBP",
+            b"VP8X",
+            &10u32.to_le_bytes(),
+            &[0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            b"VP8L",
+            &1u32.to_le_bytes(),
+            &[0x2F, 0],
+            b"EXIF",
+            &3u32.to_le_bytes(),
+            &[1, 2, 3, 0],
+        ]
+        .concat();
+
+        let stripped = strip_webp_metadata(&webp).unwrap();
+
+        assert_eq!(stripped.len(), 12 + 18 + 10);
+        assert_eq!(&stripped[4..8], &32u32.to_le_bytes());
+        assert_eq!(stripped[20], 0);
+        assert!(!stripped.windows(4).any(|w| w == b"EXIF"));
+    }
+
+    #[test]
+    fn images_with_siblings_get_picture_sources() {
+        let out_dir =
+            std::env::temp_dir().join(format!("blog-builder-picture-{}", std::process::id()));
+        fs::create_dir_all(out_dir.join("images")).unwrap();
+        fs::write(out_dir.join("images/plot.png.webp"), "").unwrap();
+        fs::write(out_dir.join("images/plot.jpg.avif"), "").unwrap();
+        fs::write(out_dir.join("images/wide-480w.png.webp"), "").unwrap();
+        let html = concat!(
+            r#"<img src="/images/plot.png" alt="Plot">"#,
+            r#"<img src="/images/other.png" alt="">"#,
+            r#"<picture><img src="/images/plot.png"></picture>"#,
+            r#"<img src="/images/wide.png" srcset="/images/wide-480w.png 480w, /images/wide.png 900w" sizes="100vw">"#,
+        );
+
+        let result = add_picture_sources(html, &out_dir);
+        fs::remove_dir_all(&out_dir).unwrap();
+
+        assert_eq!(
+            result,
+            concat!(
+                r#"<picture><source type="image/webp" srcset="/images/plot.png.webp">"#,
+                r#"<img src="/images/plot.png" alt="Plot"></picture>"#,
+                r#"<img src="/images/other.png" alt="">"#,
+                r#"<picture><img src="/images/plot.png"></picture>"#,
+                r#"<picture><source type="image/webp" srcset="/images/wide-480w.png.webp 480w, /images/wide.png 900w" sizes="100vw">"#,
+                r#"<img src="/images/wide.png" srcset="/images/wide-480w.png 480w, /images/wide.png 900w" sizes="100vw"></picture>"#,
+            )
+        );
+    }
+
+    #[test]
+    fn variants_are_named_after_their_width() {
+        assert_eq!(
+            variant_name("/images/plot.png", 480),
+            "/images/plot-480w.png"
+        );
+        assert_eq!(variant_name("/images.d/plot", 800), "/images.d/plot-800w");
+    }
+
+    #[test]
+    fn png_and_jpeg_of_one_name_get_their_own_siblings() {
+        let root =
+            std::env::temp_dir().join(format!("blog-builder-siblings-{}", std::process::id()));
+        let images = root.join("images");
+        fs::create_dir_all(&images).unwrap();
+        for (name, color, format) in [
+            ("plot.png", [200, 40, 40], ImageFormat::Png),
+            ("plot.jpg", [40, 40, 200], ImageFormat::Jpeg),
+        ] {
+            let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, image::Rgb(color)));
+            fs::write(images.join(name), encode(&image, format).unwrap()).unwrap();
+        }
+
+        let paths = [images.join("plot.png"), images.join("plot.jpg")];
+        let report = optimize_images(&paths, &root.join("cache"));
+        let png_webp = fs::read(images.join("plot.png.webp"));
+        let jpg_webp = fs::read(images.join("plot.jpg.webp"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.webp, 2);
+        assert_ne!(png_webp.unwrap(), jpg_webp.unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "lossy-webp")]
+    fn jpeg_photos_get_lossy_webp_siblings() {
+        let photo = RgbImage::from_fn(320, 240, |x, y| {
+            image::Rgb([
+                (x * 255 / 320) as u8,
+                (y * 255 / 240) as u8,
+                ((x ^ y) % 256) as u8,
+            ])
+        });
+        let jpeg = encode(&DynamicImage::ImageRgb8(photo), ImageFormat::Jpeg).unwrap();
+
+        let versions = super::optimize(&jpeg, "jpg").unwrap();
+
+        let webp = versions[0].webp.as_ref().expect("smaller than the JPEG");
+        assert_eq!(&webp[12..16], b"VP8 ");
+    }
+}
@@ -1,26 +1,19 @@
 use anyhow::Result;
 use chrono::{Datelike, NaiveDate};
 use serde_json::json;
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
-use tera::{Tera, Value};
+use tera::Tera;
 
+use crate::fingerprinting;
+use crate::optimization;
 use crate::page_generation::{extract_all_tags, Post, PostSummary};
-
-/// Tera function that outputs a placeholder for CSS inlining.
-/// Usage in template: {{ inline_css(path="/styles/global.css") }}
-/// The placeholder is replaced with actual CSS content during optimization.
-fn inline_css_placeholder(args: &HashMap<String, Value>) -> tera::Result<Value> {
-    let path = args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| tera::Error::msg("inline_css requires a 'path' argument"))?;
-    Ok(Value::String(format!("<!-- INLINE_CSS:{} -->", path)))
-}
+use crate::reproducible::build_time;
+use crate::structured_data::index_json_ld;
+use crate::urls::SiteUrls;
 
 fn format_date_display(date_str: &str) -> String {
     if let Ok(date) = NaiveDate::parse_from_str(date_str, "%Y-%m-%d") {
@@ -30,7 +23,7 @@ fn format_date_display(date_str: &str) -> String {
     }
 }
 
-pub fn build_index_page(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
+pub fn build_index_page(out_dir: &Path, posts: &Arc<Vec<Post>>, urls: &SiteUrls) -> Result<()> {
     let start = Instant::now();
 
     let post_summaries: Vec<PostSummary> = posts
@@ -41,11 +34,12 @@ pub fn build_index_page(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
             date: p.date.clone(),
             tags: p.tags.clone(),
             icon: p.icon.clone(),
+            score: None,
         })
         .collect();
 
     let all_tags = extract_all_tags(posts);
-    generate_index_page(out_dir, &post_summaries, &all_tags)?;
+    generate_index_page(out_dir, &post_summaries, &all_tags, urls)?;
 
     println!(
         "✓ Generated index.html in {:.2}s",
@@ -54,11 +48,18 @@ pub fn build_index_page(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
     Ok(())
 }
 
-pub fn generate_index_page(out_dir: &Path, posts: &[PostSummary], tags: &[String]) -> Result<()> {
+pub fn generate_index_page(
+    out_dir: &Path,
+    posts: &[PostSummary],
+    tags: &[String],
+    urls: &SiteUrls,
+) -> Result<()> {
     let mut tera = Tera::new("website/index/**/*")?;
-    tera.register_function("inline_css", inline_css_placeholder);
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
 
-    let year = chrono::Local::now().year();
+    let year = build_time().year();
 
     // Prepare posts data with tags_json
     let posts_data: Vec<serde_json::Value> = posts
@@ -80,6 +81,7 @@ pub fn generate_index_page(out_dir: &Path, posts: &[PostSummary], tags: &[String
     context.insert("tags", tags);
     context.insert("posts", &posts_data);
     context.insert("year", &year);
+    context.insert("json_ld", &index_json_ld(posts, urls)?);
 
     let html = tera.render("index.html", &context)?;
 
@@ -0,0 +1,252 @@
+//! Model-free related posts: TF-IDF cosine over each post's prose, blended
+//! with Jaccard overlap of the post tags. This is the default engine; it needs
+//! no download and produces the same map shape as `--smart-similar`.
+
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use crate::page_generation::{prose_sections, Post, PostSummary};
+use crate::search_generation::tokenize;
+use crate::similarity::{select_related, RelatedSelection};
+
+/// Share of the final score that comes from tag overlap rather than text.
+const TAG_SCORE_WEIGHT: f32 = 0.3;
+/// Title words count as if they appeared this many times in the body.
+const TITLE_TERM_BOOST: u32 = 3;
+/// Terms found in more than this share of posts do not set any post apart.
+const MAX_DOCUMENT_FREQUENCY_RATIO: f32 = 0.5;
+/// Blended score below which two posts are not considered related.
+pub const DEFAULT_MIN_SCORE: f32 = 0.05;
+
+/// Sorted `(term id, weight)` pairs with unit length.
+type SparseVector = Vec<(u32, f32)>;
+
+pub fn compute_lexical_similar_posts(
+    posts: &[Post],
+    selection: &RelatedSelection,
+) -> HashMap<String, Vec<PostSummary>> {
+    let start = Instant::now();
+
+    let vectors = tfidf_vectors(posts);
+    let score = |a: usize, b: usize| {
+        let text = sparse_dot(&vectors[a], &vectors[b]);
+        let tags = tag_jaccard(&posts[a].tags, &posts[b].tags);
+        (1.0 - TAG_SCORE_WEIGHT) * text + TAG_SCORE_WEIGHT * tags
+    };
+
+    let similar_map = posts
+        .par_iter()
+        .enumerate()
+        .map(|(i, post)| {
+            let scored: Vec<(usize, f32)> = (0..posts.len())
+                .filter(|&j| j != i)
+                .map(|j| (j, score(i, j)))
+                .filter(|&(_, score)| score > 0.0)
+                .collect();
+            let related = select_related(posts, i, &scored, score, selection);
+            (post.id.clone(), related)
+        })
+        .collect();
+
+    println!(
+        "✓ Computed lexical similarity in {:.2}s",
+        start.elapsed().as_secs_f64()
+    );
+
+    similar_map
+}
+
+/// The `k` most similar posts to every post by TF-IDF cosine alone, most
+/// similar first. Tags are left out so they can be judged against the text.
+pub fn lexical_neighbours(posts: &[Post], k: usize) -> Vec<Vec<(usize, f32)>> {
+    let vectors = tfidf_vectors(posts);
+
+    (0..posts.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut scored: Vec<(usize, f32)> = (0..posts.len())
+                .filter(|&j| j != i)
+                .map(|j| (j, sparse_dot(&vectors[i], &vectors[j])))
+                .filter(|&(_, score)| score > 0.0)
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            scored.truncate(k);
+            scored
+        })
+        .collect()
+}
+
+fn term_counts(post: &Post) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    for term in tokenize(&post.title) {
+        *counts.entry(term).or_insert(0) += TITLE_TERM_BOOST;
+    }
+    for section in prose_sections(&post.content_html) {
+        for term in tokenize(&section.heading).chain(tokenize(&section.text)) {
+            *counts.entry(term).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Builds one sublinear TF-IDF vector per post. Terms that occur in a single
+/// post cannot make two posts similar and are left out, as are terms common
+/// to most posts.
+fn tfidf_vectors(posts: &[Post]) -> Vec<SparseVector> {
+    let counts: Vec<HashMap<String, u32>> = posts.par_iter().map(term_counts).collect();
+
+    let mut document_frequency: BTreeMap<&str, u32> = BTreeMap::new();
+    for doc in &counts {
+        for term in doc.keys() {
+            *document_frequency.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    let total = posts.len() as f32;
+    let max_frequency = (MAX_DOCUMENT_FREQUENCY_RATIO * total).max(2.0);
+    let vocabulary: HashMap<&str, (u32, f32)> = document_frequency
+        .into_iter()
+        .filter(|&(_, df)| df >= 2 && df as f32 <= max_frequency)
+        .enumerate()
+        .map(|(id, (term, df))| (term, (id as u32, (total / df as f32).ln())))
+        .collect();
+
+    counts
+        .iter()
+        .map(|doc| {
+            let mut vector: SparseVector = doc
+                .iter()
+                .filter_map(|(term, &tf)| {
+                    let &(id, idf) = vocabulary.get(term.as_str())?;
+                    Some((id, (1.0 + (tf as f32).ln()) * idf))
+                })
+                .collect();
+            vector.sort_unstable_by_key(|&(id, _)| id);
+
+            let norm = vector.iter().map(|(_, w)| w * w).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                vector.iter_mut().for_each(|(_, w)| *w /= norm);
+            }
+            vector
+        })
+        .collect()
+}
+
+fn sparse_dot(a: &SparseVector, b: &SparseVector) -> f32 {
+    let (mut i, mut j, mut dot) = (0, 0, 0.0);
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                dot += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    dot
+}
+
+fn tag_jaccard(a: &[String], b: &[String]) -> f32 {
+    let shared = a.iter().filter(|tag| b.contains(tag)).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        return 0.0;
+    }
+    shared as f32 / union as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_lexical_similar_posts, tag_jaccard, DEFAULT_MIN_SCORE};
+    use crate::page_generation::Post;
+    use crate::similarity::{RelatedSelection, DEFAULT_RELATED_COUNT};
+
+    const SELECTION: RelatedSelection = RelatedSelection {
+        count: DEFAULT_RELATED_COUNT,
+        min_score: DEFAULT_MIN_SCORE,
+        diversity: 1.0,
+    };
+
+    fn post(id: &str, title: &str, body: &str, tags: &[&str]) -> Post {
+        Post {
+            id: id.to_string(),
+            title: title.to_string(),
+            date: "2024-01-01".to_string(),
+            date_modified: "2024-01-01".to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            icon: None,
+            content_html: format!("<p>{}</p>", body),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn related_posts_follow_shared_vocabulary() {
+        let posts = vec![
+            post(
+                "backprop",
+                "Backpropagation",
+                "Gradients flow backwards through neural network layers.",
+                &[],
+            ),
+            post(
+                "sourdough",
+                "Sourdough",
+                "Flour, water and a starter make bread rise.",
+                &[],
+            ),
+            post(
+                "optimizers",
+                "Optimizers",
+                "Neural network training follows gradients downhill.",
+                &[],
+            ),
+            post(
+                "baguette",
+                "Baguette",
+                "A baguette needs flour, water and a hot oven for the bread.",
+                &[],
+            ),
+            post("unrelated", "Unrelated", "Sailing on a calm lake.", &[]),
+        ];
+
+        let similar = compute_lexical_similar_posts(&posts, &SELECTION);
+
+        assert_eq!(similar["backprop"][0].id, "optimizers");
+        assert_eq!(similar["sourdough"][0].id, "baguette");
+        assert!(similar["unrelated"].is_empty());
+        assert!(similar
+            .values()
+            .all(|related| related.iter().all(|r| r.id != "unrelated")));
+    }
+
+    #[test]
+    fn tag_overlap_alone_relates_posts() {
+        let posts = vec![
+            post("a", "Alpha", "one", &["privacy", "coding"]),
+            post("b", "Beta", "two", &["privacy"]),
+            post("c", "Gamma", "three", &["art"]),
+        ];
+
+        let similar = compute_lexical_similar_posts(&posts, &SELECTION);
+
+        assert_eq!(similar["a"].len(), 1);
+        assert_eq!(similar["a"][0].id, "b");
+        assert!(similar["c"].is_empty());
+    }
+
+    #[test]
+    fn jaccard_of_tag_sets() {
+        let a = vec!["x".to_string(), "y".to_string()];
+        let b = vec!["y".to_string(), "z".to_string()];
+
+        assert!((tag_jaccard(&a, &b) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(tag_jaccard(&[], &[]), 0.0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Writes `llms.txt`, `llms-full.txt` and a markdown mirror of every post
+//! at `/posts/<id>.md`, following the llms.txt convention.
+//!
+//! Mirrors are the post source cleaned up for reading on its own: no
+//! frontmatter, wiki links turned into regular links, and every link and
+//! image pointing at the live site. Posts written in HTML are mirrored as
+//! their plain text.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::LlmsConfig;
+use crate::page_generation::{html_to_text, remove_first_h1, split_frontmatter, Post};
+use crate::urls::{absolute_url, SiteUrls, BASE_URL};
+
+const POSTS_DIR: &str = "posts";
+/// Longest post description in `llms.txt`, in characters.
+const DESCRIPTION_MAX_CHARACTERS: usize = 160;
+
+/// `[[id]]` or `[[id|label]]`.
+static WIKI_LINK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[\[([^\]|]+)(?:\|([^\]]+))?\]\]").unwrap());
+/// Destination of an inline link or image: `[text](url` or `![alt](<url>`.
+static INLINE_LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\]\(\s*<?)([^)\s>]+)").unwrap());
+/// Destination of a link reference definition: `[label]: url`.
+static LINK_DEFINITION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\s{0,3}\[[^\]]+\]:\s*<?)([^\s>]+)").unwrap());
+static HTML_URL_ATTR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)(\b(?:href|src)\s*=\s*")([^"]*)"#).unwrap());
+
+pub fn build_llms_files(
+    out_dir: &Path,
+    posts: &[Post],
+    urls: &SiteUrls,
+    config: &LlmsConfig,
+) -> Result<()> {
+    let start = Instant::now();
+
+    let titles: HashMap<&str, &str> = posts
+        .iter()
+        .map(|post| (post.id.as_str(), post.title.as_str()))
+        .collect();
+    let mirrors: Vec<String> = posts
+        .par_iter()
+        .map(|post| -> Result<String> {
+            let mirror = markdown_mirror(post, &titles, urls)?;
+            fs::write(out_dir.join(format!("posts/{}.md", post.id)), &mirror)?;
+            Ok(mirror)
+        })
+        .collect::<Result<_>>()?;
+
+    fs::write(out_dir.join("llms.txt"), llms_txt(posts, urls, config))?;
+
+    let mut full = header(config);
+    for mirror in &mirrors {
+        full.push_str("\n---\n\n");
+        full.push_str(mirror);
+    }
+    fs::write(out_dir.join("llms-full.txt"), full)?;
+
+    println!(
+        "✓ Generated llms.txt, llms-full.txt and {} markdown mirrors in {:.2}s",
+        mirrors.len(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn header(config: &LlmsConfig) -> String {
+    let mut header = format!("# {}\n\n> {}\n", config.title, config.description.trim());
+    if let Some(details) = &config.details {
+        let _ = write!(header, "\n{}\n", details.trim());
+    }
+    header
+}
+
+fn llms_txt(posts: &[Post], urls: &SiteUrls, config: &LlmsConfig) -> String {
+    let mut out = header(config);
+    out.push_str("\n## Posts\n\n");
+    for post in posts {
+        let _ = writeln!(
+            out,
+            "- [{}]({}): {}",
+            post.title,
+            urls.absolute(&format!("/posts/{}.md", post.id)),
+            description(post)
+        );
+    }
+    let _ = write!(
+        out,
+        "\n## Optional\n\n- [Full text of all posts]({})\n",
+        urls.absolute("/llms-full.txt")
+    );
+    out
+}
+
+/// Opening words of the post as one line of plain text, cut at a word boundary.
+fn description(post: &Post) -> String {
+    let text = html_to_text(&post.content_html);
+    if text.chars().count() <= DESCRIPTION_MAX_CHARACTERS {
+        return text;
+    }
+    let mut description = String::new();
+    for word in text.split(' ') {
+        if description.chars().count() + word.chars().count() + 1 > DESCRIPTION_MAX_CHARACTERS {
+            break;
+        }
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description.push_str(word);
+    }
+    format!("{}…", description.trim_end_matches([',', ';', ':', '.']))
+}
+
+/// The post as standalone markdown: its title, publication date and URL,
+/// then the cleaned-up body.
+fn markdown_mirror(post: &Post, titles: &HashMap<&str, &str>, urls: &SiteUrls) -> Result<String> {
+    let source = Path::new(POSTS_DIR).join(format!("{}.md", post.id));
+    let body = if source.is_file() {
+        let content = fs::read_to_string(&source)?;
+        let body = split_frontmatter(&content).map_or(content.as_str(), |(_, body)| body);
+        clean_markdown(&remove_first_h1(body), post, titles, urls)
+    } else {
+        html_to_text(&post.content_html)
+    };
+
+    Ok(format!(
+        "# {}\n\nPublished {} · {}\n\n{}\n",
+        post.title,
+        post.date,
+        urls.absolute(&urls.post(&post.id)),
+        body.trim()
+    ))
+}
+
+/// Resolves wiki links and makes link destinations absolute, leaving fenced
+/// code blocks as they are.
+fn clean_markdown(
+    markdown: &str,
+    post: &Post,
+    titles: &HashMap<&str, &str>,
+    urls: &SiteUrls,
+) -> String {
+    let resolve = |caps: &Captures| format!("{}{}", &caps[1], absolute_link(&caps[2], post, urls));
+
+    let mut fence: Option<&str> = None;
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| line.trim_start().starts_with(marker));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            _ => {}
+        }
+        if fence.is_some() || marker.is_some() {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let line = WIKI_LINK_RE.replace_all(line, |caps: &Captures| {
+            let id = caps[1].trim();
+            match titles.get(id) {
+                Some(title) => format!(
+                    "[{}]({})",
+                    caps.get(2).map_or(*title, |label| label.as_str().trim()),
+                    urls.absolute(&urls.post(id))
+                ),
+                None => caps[0].to_string(),
+            }
+        });
+        let line = INLINE_LINK_RE.replace_all(&line, resolve);
+        let line = LINK_DEFINITION_RE.replace(&line, resolve);
+        let line = HTML_URL_ATTR_RE.replace_all(&line, resolve);
+        lines.push(line.into_owned());
+    }
+    lines.join("\n")
+}
+
+/// Absolute URL of a link found in the post, canonical if it points into the site.
+fn absolute_link(url: &str, post: &Post, urls: &SiteUrls) -> String {
+    let url = if url.starts_with('#') {
+        format!("{}{}", urls.absolute(&urls.post(&post.id)), url)
+    } else {
+        absolute_url(url, &urls.post_base(&post.id))
+    };
+    match url.strip_prefix(BASE_URL) {
+        Some(rest) if rest.starts_with('/') => {
+            let (path, suffix) = match rest.find(['?', '#']) {
+                Some(i) => rest.split_at(i),
+                None => (rest, ""),
+            };
+            format!("{}{}", urls.absolute(&urls.canonical_path(path)), suffix)
+        }
+        _ => url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clean_markdown;
+    use crate::page_generation::Post;
+    use crate::urls::{SiteUrls, TrailingSlash};
+    use std::collections::HashMap;
+
+    fn post() -> Post {
+        Post {
+            id: "hello".to_string(),
+            title: "Hello".to_string(),
+            date: "2024-01-02".to_string(),
+            date_modified: "2024-01-02".to_string(),
+            tags: Vec::new(),
+            icon: None,
+            content_html: String::new(),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn wiki_links_resolve_to_known_posts_only() {
+        let titles = HashMap::from([("zipf", "Zipf's Law")]);
+        let urls = SiteUrls::new(TrailingSlash::Never);
+
+        assert_eq!(
+            clean_markdown(
+                "See [[zipf]], [[zipf|the law]] and [[missing]].",
+                &post(),
+                &titles,
+                &urls
+            ),
+            "See [Zipf's Law](https://seanpedersen.github.io/posts/zipf), \
+             [the law](https://seanpedersen.github.io/posts/zipf) and [[missing]]."
+        );
+    }
+
+    #[test]
+    fn links_become_absolute_and_canonical_outside_code() {
+        let urls = SiteUrls::new(TrailingSlash::Always);
+        let markdown = "![plot](/images/plot.png) [next](/posts/zipf.md#fit)\n\
+                        [ref]: ../about.html\n\
+                        ```\n[kept](/posts/zipf)\n```\n\
+                        <a href=\"#top\">top</a>";
+
+        assert_eq!(
+            clean_markdown(markdown, &post(), &HashMap::new(), &urls),
+            "![plot](https://seanpedersen.github.io/images/plot.png) \
+             [next](https://seanpedersen.github.io/posts/zipf/#fit)\n\
+             [ref]: https://seanpedersen.github.io/posts/about/\n\
+             ```\n[kept](/posts/zipf)\n```\n\
+             <a href=\"https://seanpedersen.github.io/posts/hello/#top\">top</a>"
+        );
+    }
+}
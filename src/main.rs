@@ -1,63 +1,257 @@
+// Plain vector maths, so its tests run without the feature that uses it
+#[cfg_attr(not(feature = "smart-similar"), allow(dead_code))]
+mod ann_index;
 mod class_minifier;
+mod config;
+#[cfg(feature = "smart-similar")]
+mod embedding_cache;
+mod feed_generation;
+mod fingerprinting;
 mod global_generation;
+mod image_optimization;
 mod index_generation;
+mod lexical_similarity;
+mod llms_generation;
+mod map_generation;
 mod math;
+mod math_svg;
+mod not_found_generation;
 mod optimization;
 mod page_generation;
 mod post_generation;
-mod rss_generation;
+mod precompression;
+mod reproducible;
+mod robots;
+mod search_generation;
 mod similarity;
 mod sitemap_generation;
+mod social_card_generation;
+mod structured_data;
+mod suggestions;
+mod urls;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use feed_generation::{build_feeds, FeedContent, FeedOptions};
 use global_generation::build_global_html_pages;
 use index_generation::build_index_page;
+use llms_generation::build_llms_files;
+use map_generation::build_map_page;
+use not_found_generation::build_not_found_page;
 use post_generation::build_post_pages;
-use rss_generation::build_rss_feed;
+use search_generation::build_search_index;
+use similarity::{
+    ChunkPooling, RelatedSelection, SmartSimilarOptions, DEFAULT_RELATED_COUNT,
+    DEFAULT_RELATED_DIVERSITY,
+};
 use sitemap_generation::build_sitemap_and_extras;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
+use suggestions::SuggestOptions;
+use urls::{SiteUrls, TrailingSlash};
 
 #[derive(Parser)]
 #[command(name = "blog-builder")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Use model2vec embeddings for smarter related-post recommendations
-    #[arg(long)]
+    #[arg(long, global = true)]
     smart_similar: bool,
+
+    /// Load the embedding model from a local directory instead of Hugging Face
+    #[arg(long, global = true, value_name = "DIR", requires = "smart_similar")]
+    model_path: Option<PathBuf>,
+
+    /// How chunk embeddings of a post are pooled into one vector
+    #[arg(long, global = true, value_enum, default_value_t = ChunkPooling::Mean)]
+    embedding_pooling: ChunkPooling,
+
+    /// Weight of the title embedding relative to the post body
+    #[arg(long, global = true, default_value_t = 0.3)]
+    title_weight: f32,
+
+    /// Weight of the tag embedding relative to the post body
+    #[arg(long, global = true, default_value_t = 0.2)]
+    tag_weight: f32,
+
+    /// Maximum number of related posts listed under each post
+    #[arg(long, default_value_t = DEFAULT_RELATED_COUNT)]
+    related_count: usize,
+
+    /// Minimum similarity for a post to be listed as related (engine-specific default)
+    #[arg(long)]
+    related_min_score: Option<f32>,
+
+    /// Relevance vs. variety of related posts, from 0.0 (varied) to 1.0 (most similar)
+    #[arg(long, default_value_t = DEFAULT_RELATED_DIVERSITY)]
+    related_diversity: f32,
+
+    /// Whether feeds carry whole posts or only summaries
+    #[arg(long, value_enum, default_value_t = FeedContent::Full)]
+    feed_content: FeedContent,
+
+    /// Maximum number of entries in each feed (all posts if unset)
+    #[arg(long, value_name = "N")]
+    feed_limit: Option<usize>,
+
+    /// Also write updates.xml, a feed of posts revised after publication
+    #[arg(long)]
+    feed_updates: bool,
+
+    /// Whether URLs of directory pages such as posts end in a slash
+    #[arg(long, value_enum, default_value_t = TrailingSlash::Never)]
+    trailing_slash: TrailingSlash,
+
+    /// Directory build caches (social cards, images, embeddings) are kept in
+    #[arg(long, global = true, default_value = ".cache", value_name = "DIR")]
+    cache_dir: PathBuf,
+
+    /// Directory the site is written to
+    #[arg(long, default_value = "out", value_name = "DIR")]
+    out_dir: PathBuf,
+
+    /// Build twice into temporary directories and fail if the outputs differ
+    #[arg(long, conflicts_with_all = ["out_dir", "cache_dir"])]
+    verify_reproducible: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Suggest missing tags and report near-duplicate posts instead of building the site
+    Suggest(SuggestArgs),
+}
+
+#[derive(Args)]
+struct SuggestArgs {
+    /// Write the suggested tags into the post sources
+    #[arg(long)]
+    apply: bool,
+
+    /// Similarity from which two posts count as near-duplicates (engine-specific default)
+    #[arg(long)]
+    duplicate_threshold: Option<f32>,
+
+    /// Number of most similar tagged posts that vote on the tags of each post
+    #[arg(long, default_value_t = 5)]
+    neighbours: usize,
 }
 
+/// Near-duplicate threshold for TF-IDF cosine similarity.
+const LEXICAL_DUPLICATE_THRESHOLD: f32 = 0.8;
+/// Near-duplicate threshold for embedding cosine similarity.
+const EMBEDDING_DUPLICATE_THRESHOLD: f32 = 0.95;
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(Command::Suggest(args)) = &cli.command {
+        return suggest(&cli, args);
+    }
+
+    if cli.verify_reproducible {
+        return reproducible::verify_reproducible();
+    }
+
     let total_start = Instant::now();
-    let out_dir = PathBuf::from("out");
+    build_site(&cli, &cli.out_dir)?;
 
+    println!("\nCompleted in {:.2}s", total_start.elapsed().as_secs_f64());
+    println!("Output directory: {}/", cli.out_dir.display());
+
+    Ok(())
+}
+
+fn build_site(cli: &Cli, out_dir: &Path) -> Result<()> {
     println!("Starting static site generation...\n");
-    setup_output_directory(&out_dir)?;
+    setup_output_directory(out_dir)?;
 
     // Build pipeline
-    let posts = page_generation::get_posts_data(&out_dir)?;
+    let config = config::load_site_config(Path::new(config::CONFIG_PATH))?;
+    let urls = SiteUrls::new(cli.trailing_slash);
+    let posts = page_generation::get_posts_data(&urls)?;
 
-    let similar_map = if cli.smart_similar {
-        Some(similarity::compute_similar_posts(&posts)?)
+    let selection = RelatedSelection {
+        count: cli.related_count,
+        min_score: cli.related_min_score.unwrap_or(if cli.smart_similar {
+            similarity::DEFAULT_MIN_SIMILARITY
+        } else {
+            lexical_similarity::DEFAULT_MIN_SCORE
+        }),
+        diversity: cli.related_diversity.clamp(0.0, 1.0),
+    };
+    let (similar_map, embeddings) = if cli.smart_similar {
+        let options = smart_similar_options(cli);
+        let smart = similarity::compute_similar_posts(&posts, &options, &selection)?;
+        (smart.similar_map, Some(smart.embeddings))
     } else {
-        None
+        let similar_map = lexical_similarity::compute_lexical_similar_posts(&posts, &selection);
+        (similar_map, None)
     };
 
-    build_index_page(&out_dir, &posts)?;
-    build_post_pages(&out_dir, &posts, similar_map.as_ref())?;
-    build_global_html_pages(&out_dir)?;
-    build_rss_feed(&out_dir, &posts)?;
-    build_sitemap_and_extras(&out_dir, &posts)?;
-    optimization::optimize_website_assets(&out_dir)?;
+    build_index_page(out_dir, &posts, &urls)?;
+    social_card_generation::build_social_cards(out_dir, &cli.cache_dir, &posts)?;
+    build_post_pages(out_dir, &posts, &similar_map, &urls)?;
+    build_global_html_pages(out_dir, &urls)?;
+    build_not_found_page(out_dir, &posts, &urls)?;
+    let feed_options = FeedOptions {
+        content: cli.feed_content,
+        max_items: cli.feed_limit,
+        include_updates: cli.feed_updates,
+    };
+    build_feeds(out_dir, &posts, &feed_options, &urls)?;
+    build_search_index(out_dir, &posts, &urls)?;
+    if let Some(embeddings) = &embeddings {
+        build_map_page(out_dir, &posts, embeddings, &urls)?;
+    }
+    // Last, so the sitemap and the link check see every page
+    robots::apply_noindex(out_dir, &config.robots, &urls)?;
+    build_sitemap_and_extras(out_dir, &posts, &urls, &config.robots)?;
+    let indexed_posts = robots::indexed_posts(&posts, &config.robots, &urls);
+    build_llms_files(out_dir, &indexed_posts, &urls, &config.llms)?;
+    urls::check_internal_links(out_dir, &urls)?;
+    optimization::optimize_website_assets(out_dir, &cli.cache_dir)?;
+    // After optimization, so the hashes cover the published bytes
+    fingerprinting::fingerprint_assets(out_dir)?;
+    // Last, as it compresses the files as published
+    precompression::precompress_assets(out_dir)?;
+    Ok(())
+}
 
-    println!("\nCompleted in {:.2}s", total_start.elapsed().as_secs_f64());
-    println!("Output directory: {}/", out_dir.display());
+fn suggest(cli: &Cli, args: &SuggestArgs) -> Result<()> {
+    let posts = page_generation::get_posts_data(&SiteUrls::new(cli.trailing_slash))?;
 
-    Ok(())
+    // Every other post, so no near-duplicate pair is missed; tag votes only
+    // use the nearest tagged ones
+    let k = posts.len();
+    let (neighbours, default_threshold) = if cli.smart_similar {
+        let options = smart_similar_options(cli);
+        let neighbours = similarity::compute_post_neighbours(&posts, &options, k)?;
+        (neighbours, EMBEDDING_DUPLICATE_THRESHOLD)
+    } else {
+        let neighbours = lexical_similarity::lexical_neighbours(&posts, k);
+        (neighbours, LEXICAL_DUPLICATE_THRESHOLD)
+    };
+
+    let options = SuggestOptions {
+        apply: args.apply,
+        duplicate_threshold: args.duplicate_threshold.unwrap_or(default_threshold),
+        neighbours: args.neighbours,
+    };
+    suggestions::run_suggest(&posts, &neighbours, &options)
+}
+
+fn smart_similar_options(cli: &Cli) -> SmartSimilarOptions {
+    SmartSimilarOptions {
+        model_path: cli.model_path.clone(),
+        cache_dir: cli.cache_dir.clone(),
+        pooling: cli.embedding_pooling,
+        title_weight: cli.title_weight,
+        tag_weight: cli.tag_weight,
+    }
 }
 
-fn setup_output_directory(out_dir: &PathBuf) -> Result<()> {
+fn setup_output_directory(out_dir: &Path) -> Result<()> {
     if out_dir.exists() {
         std::fs::remove_dir_all(out_dir)?;
     }
@@ -0,0 +1,553 @@
+//! Builds the `/map/` page: every post as a dot on a 2D map of topics.
+//!
+//! Post embeddings from `--smart-similar` are reduced with PCA, laid out with
+//! t-SNE (initialised from the PCA projection, so the result is deterministic),
+//! grouped by k-means and rendered as an inline SVG. Each cluster is labelled
+//! with the tags most frequent among its posts.
+
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+use tera::Tera;
+
+use crate::fingerprinting;
+use crate::optimization;
+use crate::page_generation::Post;
+use crate::urls::SiteUrls;
+
+/// Dimensions kept by PCA before the t-SNE layout.
+const PCA_DIMS: usize = 30;
+const PCA_ITERATIONS: usize = 100;
+
+const TSNE_PERPLEXITY: f64 = 30.0;
+const TSNE_ITERATIONS: usize = 750;
+/// P is scaled up for the first iterations so clusters form before they settle.
+const TSNE_EXAGGERATION: f64 = 12.0;
+const TSNE_EXAGGERATION_ITERATIONS: usize = 250;
+/// t-SNE is quadratic in the number of posts; larger archives use the PCA projection as is.
+const MAX_TSNE_POSTS: usize = 3_000;
+
+const MAX_CLUSTERS: usize = 12;
+const KMEANS_ITERATIONS: usize = 100;
+const TAGS_PER_LABEL: usize = 2;
+
+const MAP_WIDTH: f64 = 1000.0;
+const MAP_HEIGHT: f64 = 700.0;
+const MAP_MARGIN: f64 = 40.0;
+
+type Point = [f64; 2];
+
+#[derive(Serialize)]
+struct MapPoint<'a> {
+    id: &'a str,
+    title: &'a str,
+    x: f64,
+    y: f64,
+    color: String,
+}
+
+#[derive(Serialize)]
+struct ClusterLabel {
+    text: String,
+    x: f64,
+    y: f64,
+}
+
+pub fn build_map_page(
+    out_dir: &Path,
+    posts: &[Post],
+    embeddings: &[Vec<f32>],
+    urls: &SiteUrls,
+) -> Result<()> {
+    let start = Instant::now();
+    if posts.is_empty() {
+        return Ok(());
+    }
+
+    let vectors: Vec<Vec<f64>> = embeddings
+        .iter()
+        .map(|v| v.iter().map(|&x| x as f64).collect())
+        .collect();
+    let reduced = pca(&vectors, PCA_DIMS);
+    let layout = if posts.len() <= MAX_TSNE_POSTS {
+        tsne(&reduced)
+    } else {
+        reduced.iter().map(|v| [v[0], v[1]]).collect()
+    };
+
+    let cluster_count = (posts.len() as f64 / 2.0).sqrt().round() as usize;
+    let (assignments, centroids) = kmeans(&layout, cluster_count.clamp(1, MAX_CLUSTERS));
+    let to_svg = fit_to_map(&layout);
+    let color =
+        |cluster: usize| format!("hsl({}, 65%, 55%)", cluster * 360 / centroids.len().max(1));
+
+    let points: Vec<MapPoint> = posts
+        .iter()
+        .zip(&layout)
+        .zip(&assignments)
+        .map(|((post, point), &cluster)| {
+            let [x, y] = to_svg(point);
+            MapPoint {
+                id: &post.id,
+                title: &post.title,
+                x,
+                y,
+                color: color(cluster),
+            }
+        })
+        .collect();
+
+    let labels: Vec<ClusterLabel> = cluster_labels(posts, &assignments, centroids.len())
+        .into_iter()
+        .zip(&centroids)
+        .filter(|(text, _)| !text.is_empty())
+        .map(|(text, centroid)| {
+            let [x, y] = to_svg(centroid);
+            ClusterLabel { text, x, y }
+        })
+        .collect();
+
+    let map_dir = out_dir.join("map");
+    fs::create_dir_all(&map_dir)?;
+    generate_map_page(&map_dir, &points, &labels, urls)?;
+
+    println!(
+        "✓ Generated map of {} posts in {} clusters in {:.2}s",
+        points.len(),
+        centroids.len(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn generate_map_page(
+    map_dir: &Path,
+    points: &[MapPoint],
+    labels: &[ClusterLabel],
+    urls: &SiteUrls,
+) -> Result<()> {
+    let mut tera = Tera::new("website/map/**/*.html")?;
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
+
+    let mut context = tera::Context::new();
+    context.insert("points", points);
+    context.insert("labels", labels);
+    context.insert("width", &MAP_WIDTH);
+    context.insert("height", &MAP_HEIGHT);
+    let html = tera.render("map.html", &context)?;
+
+    let mut file = BufWriter::new(File::create(map_dir.join("index.html"))?);
+    write!(file, "{}", html)?;
+
+    Ok(())
+}
+
+/// Projects `vectors` onto their top `dims` principal components, found by
+/// power iteration with deflation on the covariance matrix.
+fn pca(vectors: &[Vec<f64>], dims: usize) -> Vec<Vec<f64>> {
+    let n = vectors.len();
+    let d = vectors.first().map_or(0, Vec::len);
+    if n == 0 || d == 0 {
+        return vec![vec![0.0; 2]; n];
+    }
+
+    let mean: Vec<f64> = (0..d)
+        .map(|k| vectors.iter().map(|v| v[k]).sum::<f64>() / n as f64)
+        .collect();
+    let centered: Vec<Vec<f64>> = vectors
+        .iter()
+        .map(|v| v.iter().zip(&mean).map(|(x, m)| x - m).collect())
+        .collect();
+
+    let mut covariance: Vec<Vec<f64>> = (0..d)
+        .into_par_iter()
+        .map(|a| {
+            (0..d)
+                .map(|b| centered.iter().map(|v| v[a] * v[b]).sum::<f64>() / n as f64)
+                .collect()
+        })
+        .collect();
+
+    // At least two components so there is always a 2D fallback layout
+    let components: Vec<Vec<f64>> = (0..dims.min(d).max(2))
+        .map(|c| {
+            let mut component: Vec<f64> = (0..d).map(|k| 1.0 + ((k + c) % 7) as f64).collect();
+            let mut eigenvalue = 0.0;
+            for _ in 0..PCA_ITERATIONS {
+                let next: Vec<f64> = covariance.iter().map(|row| dot(row, &component)).collect();
+                eigenvalue = norm(&next);
+                if eigenvalue < 1e-12 {
+                    return vec![0.0; d];
+                }
+                component = next.iter().map(|x| x / eigenvalue).collect();
+            }
+            for (a, row) in covariance.iter_mut().enumerate() {
+                for (b, value) in row.iter_mut().enumerate() {
+                    *value -= eigenvalue * component[a] * component[b];
+                }
+            }
+            component
+        })
+        .collect();
+
+    centered
+        .iter()
+        .map(|v| components.iter().map(|c| dot(v, c)).collect())
+        .collect()
+}
+
+/// Exact t-SNE (van der Maaten & Hinton 2008) starting from the first two
+/// principal components.
+fn tsne(reduced: &[Vec<f64>]) -> Vec<Point> {
+    let n = reduced.len();
+    let mut layout: Vec<Point> = reduced.iter().map(|v| [v[0], v[1]]).collect();
+    if n < 4 {
+        return layout;
+    }
+
+    // Rescale the initial layout to a small spread, as t-SNE expects
+    let spread = (layout.iter().map(|p| p[0] * p[0]).sum::<f64>() / n as f64).sqrt();
+    if spread > 0.0 {
+        for p in &mut layout {
+            p[0] *= 1e-4 / spread;
+            p[1] *= 1e-4 / spread;
+        }
+    }
+
+    let affinities = joint_affinities(reduced);
+    let learning_rate = (n as f64 / TSNE_EXAGGERATION).max(50.0);
+    let mut velocity: Vec<Point> = vec![[0.0; 2]; n];
+    let mut gains: Vec<Point> = vec![[1.0; 2]; n];
+
+    for iteration in 0..TSNE_ITERATIONS {
+        let (exaggeration, momentum) = if iteration < TSNE_EXAGGERATION_ITERATIONS {
+            (TSNE_EXAGGERATION, 0.5)
+        } else {
+            (1.0, 0.8)
+        };
+
+        // Student-t kernel; summed row by row so the total does not depend on thread scheduling
+        let row_sums: Vec<f64> = (0..n)
+            .into_par_iter()
+            .map(|i| {
+                (0..n)
+                    .filter(|&j| j != i)
+                    .map(|j| 1.0 / (1.0 + squared_distance(&layout[i], &layout[j])))
+                    .sum()
+            })
+            .collect();
+        let normalizer: f64 = row_sums.iter().sum();
+
+        let gradients: Vec<Point> = (0..n)
+            .into_par_iter()
+            .map(|i| {
+                let mut gradient = [0.0; 2];
+                for j in (0..n).filter(|&j| j != i) {
+                    let kernel = 1.0 / (1.0 + squared_distance(&layout[i], &layout[j]));
+                    let force =
+                        (exaggeration * affinities[i][j] - kernel / normalizer) * kernel * 4.0;
+                    gradient[0] += force * (layout[i][0] - layout[j][0]);
+                    gradient[1] += force * (layout[i][1] - layout[j][1]);
+                }
+                gradient
+            })
+            .collect();
+
+        for i in 0..n {
+            for axis in 0..2 {
+                let gain = &mut gains[i][axis];
+                *gain = if (gradients[i][axis] > 0.0) != (velocity[i][axis] > 0.0) {
+                    *gain + 0.2
+                } else {
+                    (*gain * 0.8).max(0.01)
+                };
+                velocity[i][axis] =
+                    momentum * velocity[i][axis] - learning_rate * *gain * gradients[i][axis];
+                layout[i][axis] += velocity[i][axis];
+            }
+        }
+    }
+
+    layout
+}
+
+/// Symmetric t-SNE input affinities, each row calibrated to the target perplexity.
+fn joint_affinities(reduced: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = reduced.len();
+    let perplexity = TSNE_PERPLEXITY.min((n - 1) as f64 / 3.0);
+    let target_entropy = perplexity.ln();
+
+    let conditional: Vec<Vec<f64>> = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let distances: Vec<f64> = (0..n)
+                .map(|j| {
+                    reduced[i]
+                        .iter()
+                        .zip(&reduced[j])
+                        .map(|(a, b)| (a - b) * (a - b))
+                        .sum()
+                })
+                .collect();
+
+            // Binary search for the Gaussian precision that yields the perplexity
+            let (mut beta, mut low, mut high) = (1.0, 0.0, f64::INFINITY);
+            let mut row = vec![0.0; n];
+            for _ in 0..64 {
+                for j in 0..n {
+                    row[j] = if j == i {
+                        0.0
+                    } else {
+                        (-beta * distances[j]).exp()
+                    };
+                }
+                let sum: f64 = row.iter().sum::<f64>().max(1e-300);
+                let entropy = beta * row.iter().zip(&distances).map(|(p, d)| p * d).sum::<f64>()
+                    / sum
+                    + sum.ln();
+                row.iter_mut().for_each(|p| *p /= sum);
+
+                if (entropy - target_entropy).abs() < 1e-5 {
+                    break;
+                }
+                if entropy > target_entropy {
+                    low = beta;
+                    beta = if high.is_finite() {
+                        (beta + high) / 2.0
+                    } else {
+                        beta * 2.0
+                    };
+                } else {
+                    high = beta;
+                    beta = (beta + low) / 2.0;
+                }
+            }
+            row
+        })
+        .collect();
+
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| ((conditional[i][j] + conditional[j][i]) / (2.0 * n as f64)).max(1e-12))
+                .collect()
+        })
+        .collect()
+}
+
+/// Lloyd's k-means, seeded with the point nearest the mean and then
+/// repeatedly the point farthest from all seeds, so no randomness is involved.
+fn kmeans(points: &[Point], k: usize) -> (Vec<usize>, Vec<Point>) {
+    if points.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let k = k.min(points.len());
+
+    let mean = centroid(points.iter());
+    let first = nearest(&mean, points);
+    let mut centroids = vec![points[first]];
+    while centroids.len() < k {
+        let farthest = (0..points.len())
+            .max_by(|&a, &b| {
+                let da = squared_distance(&points[a], &centroids[nearest(&points[a], &centroids)]);
+                let db = squared_distance(&points[b], &centroids[nearest(&points[b], &centroids)]);
+                da.total_cmp(&db)
+            })
+            .unwrap_or(0);
+        centroids.push(points[farthest]);
+    }
+
+    let mut assignments = vec![0; points.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        let next: Vec<usize> = points.iter().map(|p| nearest(p, &centroids)).collect();
+        let changed = next != assignments;
+        assignments = next;
+
+        for (c, center) in centroids.iter_mut().enumerate() {
+            let mut members = points
+                .iter()
+                .zip(&assignments)
+                .filter(|(_, &a)| a == c)
+                .map(|(p, _)| p)
+                .peekable();
+            // An emptied cluster keeps its old centre
+            if members.peek().is_some() {
+                *center = centroid(members);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    (assignments, centroids)
+}
+
+/// Labels each cluster with its most frequent tags; ties go to the tag that sorts first.
+fn cluster_labels(posts: &[Post], assignments: &[usize], clusters: usize) -> Vec<String> {
+    (0..clusters)
+        .map(|c| {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for (post, _) in posts.iter().zip(assignments).filter(|(_, &a)| a == c) {
+                for tag in &post.tags {
+                    *counts.entry(tag).or_insert(0) += 1;
+                }
+            }
+            let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            counts
+                .iter()
+                .take(TAGS_PER_LABEL)
+                .map(|(tag, _)| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// Returns a function mapping layout coordinates into the SVG viewBox,
+/// keeping the aspect ratio and centring the layout.
+fn fit_to_map(layout: &[Point]) -> impl Fn(&Point) -> Point {
+    let (mut min, mut max) = ([f64::MAX; 2], [f64::MIN; 2]);
+    for p in layout {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    let range = [(max[0] - min[0]).max(1e-9), (max[1] - min[1]).max(1e-9)];
+    let scale =
+        ((MAP_WIDTH - 2.0 * MAP_MARGIN) / range[0]).min((MAP_HEIGHT - 2.0 * MAP_MARGIN) / range[1]);
+    let offset = [
+        (MAP_WIDTH - range[0] * scale) / 2.0,
+        (MAP_HEIGHT - range[1] * scale) / 2.0,
+    ];
+
+    move |p| {
+        [
+            offset[0] + (p[0] - min[0]) * scale,
+            offset[1] + (p[1] - min[1]) * scale,
+        ]
+    }
+}
+
+fn centroid<'a>(points: impl Iterator<Item = &'a Point>) -> Point {
+    let (mut sum, mut count) = ([0.0; 2], 0.0);
+    for p in points {
+        sum[0] += p[0];
+        sum[1] += p[1];
+        count += 1.0;
+    }
+    [sum[0] / count, sum[1] / count]
+}
+
+fn nearest(point: &Point, candidates: &[Point]) -> usize {
+    (0..candidates.len())
+        .min_by(|&a, &b| {
+            squared_distance(point, &candidates[a])
+                .total_cmp(&squared_distance(point, &candidates[b]))
+        })
+        .unwrap_or(0)
+}
+
+fn squared_distance(a: &Point, b: &Point) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn norm(v: &[f64]) -> f64 {
+    dot(v, v).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cluster_labels, kmeans, pca, tsne, Point};
+    use crate::page_generation::Post;
+
+    /// Two tight groups of points in 8 dimensions, far apart along the first axis.
+    fn two_groups(per_group: usize) -> Vec<Vec<f64>> {
+        (0..per_group * 2)
+            .map(|i| {
+                let side = if i < per_group { -5.0 } else { 5.0 };
+                (0..8)
+                    .map(|k| {
+                        if k == 0 {
+                            side
+                        } else {
+                            ((i * 7 + k * 3) % 5) as f64 * 0.1
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pca_puts_largest_variance_first() {
+        let reduced = pca(&two_groups(5), 3);
+
+        assert_eq!(reduced[0].len(), 3);
+        assert!(reduced[0][0].abs() > 4.0);
+        assert!(reduced[0][0].signum() != reduced[9][0].signum());
+        assert!(reduced.iter().all(|v| v[1].abs() < 1.0));
+    }
+
+    #[test]
+    fn tsne_and_kmeans_keep_groups_apart() {
+        let layout = tsne(&pca(&two_groups(10), 5));
+
+        let (assignments, centroids) = kmeans(&layout, 2);
+
+        assert_eq!(centroids.len(), 2);
+        assert!(assignments[..10].iter().all(|&a| a == assignments[0]));
+        assert!(assignments[10..].iter().all(|&a| a == assignments[10]));
+        assert_ne!(assignments[0], assignments[10]);
+    }
+
+    #[test]
+    fn clusters_are_labelled_by_most_frequent_tags() {
+        let post = |tags: &[&str]| Post {
+            id: String::new(),
+            title: String::new(),
+            date: String::new(),
+            date_modified: String::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            icon: None,
+            content_html: String::new(),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        };
+        let posts = vec![
+            post(&["rust", "web"]),
+            post(&["rust", "cli"]),
+            post(&["cli"]),
+            post(&[]),
+        ];
+
+        let labels = cluster_labels(&posts, &[0, 0, 0, 1], 2);
+
+        assert_eq!(labels, vec!["#cli #rust".to_string(), String::new()]);
+    }
+
+    #[test]
+    fn kmeans_never_asks_for_more_clusters_than_points() {
+        let points: Vec<Point> = vec![[0.0, 0.0], [1.0, 1.0]];
+
+        let (assignments, centroids) = kmeans(&points, 5);
+
+        assert_eq!(centroids.len(), 2);
+        assert_eq!(assignments, vec![0, 1]);
+    }
+}
@@ -119,12 +119,22 @@ fn normalize(latex: &str) -> String {
 }
 
 /// Converts a LaTeX fragment to MathML, or `None` if it cannot be rendered.
+/// The source is kept in the `alttext` attribute, for screen readers and for
+/// outputs that replace the MathML with an image.
 pub fn to_mathml(latex: &str, style: DisplayStyle) -> Option<String> {
     let mathml = latex_to_mathml(&normalize(latex), style).ok()?;
     if mathml.contains(PARSE_ERROR_MARKER) {
         return None;
     }
-    Some(expand_self_closing(&mathml))
+    let alttext = format!(r#"<math alttext="{}" "#, escape_attribute(latex.trim()));
+    Some(expand_self_closing(&mathml.replacen("<math ", &alttext, 1)))
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 #[cfg(test)]
@@ -166,6 +176,11 @@ mod tests {
         assert_eq!(normalize(r"a\,b\\c"), r"a\,b\\c");
     }
 
+    #[test]
+    fn keeps_latex_source_as_alttext() {
+        assert!(render(" a<b ").starts_with(r#"<math alttext="a&lt;b" "#));
+    }
+
     #[test]
     fn reports_unrenderable_latex() {
         assert!(to_mathml(r"\thiscommanddoesnotexist", DisplayStyle::Inline).is_none());
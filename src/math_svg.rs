@@ -0,0 +1,1005 @@
+//! MathML -> SVG rendering for outputs that cannot display MathML.
+//!
+//! Feed readers and email clients ignore `<math>`, so syndicated posts carry
+//! their equations as SVG images instead. This lays out the MathML subset
+//! `latex2mathml` emits (scripts, fractions, radicals, accents, stretchy
+//! fences, matrices) with simplified TeX spacing rules and estimated glyph
+//! widths; the glyphs themselves are drawn by the reader's serif font.
+//! Lengths are in em until the final SVG is written.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Pixels per em of the rendered images.
+const FONT_SIZE: f32 = 18.0;
+/// Size of sub/superscripts and limits relative to their base.
+const SCRIPT_SCALE: f32 = 0.7;
+const MIN_SCALE: f32 = 0.5;
+/// Height of the math axis (fraction bars, centered operators) above the baseline.
+const AXIS_HEIGHT: f32 = 0.25;
+const GLYPH_ASCENT: f32 = 0.72;
+const GLYPH_DESCENT: f32 = 0.22;
+const RULE_THICKNESS: f32 = 0.05;
+/// Blank border around the equation, so nothing touches the image edge.
+const PADDING: f32 = 0.1;
+const FONT_FAMILY: &str =
+    "'Latin Modern Math','STIX Two Math','Cambria Math','Times New Roman',serif";
+
+static ATTRIBUTE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"([a-zA-Z][\w:-]*)="([^"]*)""#).unwrap());
+
+/// A rendered equation; sizes are in pixels.
+pub struct MathImage {
+    pub svg: String,
+    pub width: u32,
+    pub height: u32,
+    /// Distance from the bottom of the image up to the text baseline.
+    pub depth: f32,
+}
+
+/// Renders a `<math>` element to SVG, or `None` if it cannot be parsed.
+pub fn mathml_to_svg(mathml: &str) -> Option<MathImage> {
+    let math = parse(mathml)?;
+    let style = Style {
+        scale: 1.0,
+        display: math.attr("display") == Some("block"),
+    };
+    let layout = layout_row(&math.children, style);
+    Some(write_svg(&layout))
+}
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn text(&self) -> String {
+        self.children
+            .iter()
+            .map(|child| match child {
+                Node::Text(text) => text.clone(),
+                Node::Element(element) => element.text(),
+            })
+            .collect()
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+/// Parses the `<math>` element. The parser is deliberately lenient:
+/// latex2mathml emits unescaped `<` operators (`<mo><</mo>`) and, for sized
+/// parentheses, a mismatched closing tag, so any closing tag closes the
+/// innermost open element.
+fn parse(mathml: &str) -> Option<Element> {
+    let mut stack = vec![Element::default()];
+    let mut rest = mathml;
+
+    while !rest.is_empty() {
+        let starts_tag = rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        if !starts_tag {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |i| i + first);
+            let text = decode_entities(&rest[..end]);
+            stack.last_mut()?.children.push(Node::Text(text));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = rest.find('>')?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('/') {
+            if stack.len() > 1 {
+                let element = stack.pop()?;
+                stack.last_mut()?.children.push(Node::Element(element));
+            }
+            continue;
+        }
+
+        let (tag, self_closing) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let element = Element {
+            name: tag[..name_end].to_string(),
+            attributes: ATTRIBUTE_RE
+                .captures_iter(&tag[name_end..])
+                .map(|caps| (caps[1].to_string(), decode_entities(&caps[2])))
+                .collect(),
+            children: Vec::new(),
+        };
+        if self_closing {
+            stack.last_mut()?.children.push(Node::Element(element));
+        } else {
+            stack.push(element);
+        }
+    }
+
+    // Close anything left open, then look for the math root
+    while stack.len() > 1 {
+        let element = stack.pop()?;
+        stack.last_mut()?.children.push(Node::Element(element));
+    }
+    stack
+        .pop()?
+        .children
+        .into_iter()
+        .find_map(|child| match child {
+            Node::Element(element) if element.name == "math" => Some(element),
+            _ => None,
+        })
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        return char::from_u32(u32::from_str_radix(hex, 16).ok()?);
+    }
+    if let Some(decimal) = name.strip_prefix('#') {
+        return char::from_u32(decimal.parse().ok()?);
+    }
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "lang" => Some('⟨'),
+        "rang" => Some('⟩'),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    /// Font size relative to the base size.
+    scale: f32,
+    display: bool,
+}
+
+impl Style {
+    fn script(self) -> Style {
+        Style {
+            scale: (self.scale * SCRIPT_SCALE).max(MIN_SCALE),
+            display: false,
+        }
+    }
+}
+
+/// Something drawn in a [`LayoutBox`]. Coordinates are relative to the box
+/// origin on its baseline, with `y` pointing up.
+#[derive(Debug, Clone)]
+enum Item {
+    /// Text centered on `x`, with its baseline at `y`. A `stretch` above 1
+    /// scales the glyph vertically around the math axis.
+    Glyph {
+        x: f32,
+        y: f32,
+        text: String,
+        scale: f32,
+        italic: bool,
+        bold: bool,
+        stretch: f32,
+    },
+    /// A horizontal bar whose vertical center is at `y`.
+    Rule {
+        x: f32,
+        y: f32,
+        width: f32,
+        thickness: f32,
+    },
+    Polyline {
+        points: Vec<(f32, f32)>,
+        thickness: f32,
+    },
+}
+
+impl Item {
+    fn translate(&mut self, dx: f32, dy: f32) {
+        match self {
+            Item::Glyph { x, y, .. } | Item::Rule { x, y, .. } => {
+                *x += dx;
+                *y += dy;
+            }
+            Item::Polyline { points, .. } => {
+                for (x, y) in points {
+                    *x += dx;
+                    *y += dy;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LayoutBox {
+    width: f32,
+    ascent: f32,
+    descent: f32,
+    items: Vec<Item>,
+}
+
+impl LayoutBox {
+    fn empty(width: f32) -> LayoutBox {
+        LayoutBox {
+            width,
+            ..LayoutBox::default()
+        }
+    }
+
+    /// Copies `other`'s items into `self`, with `other`'s origin at `(x, y)`.
+    fn place(&mut self, other: LayoutBox, x: f32, y: f32) {
+        self.items.extend(other.items.into_iter().map(|mut item| {
+            item.translate(x, y);
+            item
+        }));
+    }
+
+    /// Stacks `child` centered over this box's width, its baseline at `y`.
+    fn place_centered(&mut self, child: LayoutBox, width: f32, y: f32) {
+        let x = (width - child.width) / 2.0;
+        self.ascent = self.ascent.max(y + child.ascent);
+        self.descent = self.descent.max(child.descent - y);
+        self.place(child, x, y);
+    }
+}
+
+fn layout_element(element: &Element, style: Style) -> LayoutBox {
+    let children: Vec<&Element> = element.elements().collect();
+    let child = |i: usize, style: Style| {
+        children
+            .get(i)
+            .map_or_else(LayoutBox::default, |c| layout_element(c, style))
+    };
+
+    match element.name.as_str() {
+        "mi" => layout_identifier(element, style),
+        "mn" => layout_text(&element.text(), style, false, false),
+        "mtext" => layout_text(&element.text(), style, false, false),
+        "mo" => layout_operator(element, style, 1.0),
+        "mspace" => {
+            let width = element
+                .attr("width")
+                .and_then(|w| w.strip_suffix("em"))
+                .and_then(|w| w.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            LayoutBox::empty(width * style.scale)
+        }
+        "msub" => layout_scripts(child(0, style), Some(child(1, style.script())), None, style),
+        "msup" => layout_scripts(child(0, style), None, Some(child(1, style.script())), style),
+        "msubsup" => layout_scripts(
+            child(0, style),
+            Some(child(1, style.script())),
+            Some(child(2, style.script())),
+            style,
+        ),
+        "mover" | "munder" | "munderover" => layout_under_over(element, &children, style),
+        "mfrac" => {
+            let inner = if style.display { style } else { style.script() };
+            let thickness = match element.attr("linethickness") {
+                Some("0") | Some("0em") => 0.0,
+                _ => RULE_THICKNESS,
+            };
+            layout_fraction(child(0, inner), child(1, inner), thickness, style)
+        }
+        "msqrt" => layout_radical(layout_row(&element.children, style), None, style),
+        "mroot" => layout_radical(
+            child(0, style),
+            Some(child(
+                1,
+                Style {
+                    scale: (style.scale * 0.5).max(MIN_SCALE),
+                    display: false,
+                },
+            )),
+            style,
+        ),
+        "mtable" => layout_table(element, style),
+        "mstyle" => {
+            let style = match element.attr("displaystyle") {
+                Some("true") => Style {
+                    display: true,
+                    ..style
+                },
+                Some("false") => Style {
+                    display: false,
+                    ..style
+                },
+                _ => style,
+            };
+            layout_row(&element.children, style)
+        }
+        "mphantom" => {
+            let mut layout = layout_row(&element.children, style);
+            layout.items.clear();
+            layout
+        }
+        _ => layout_row(&element.children, style),
+    }
+}
+
+/// Lays out children side by side. Stretchy fences are sized last, to the
+/// height of everything else in the row.
+fn layout_row(children: &[Node], style: Style) -> LayoutBox {
+    let elements: Vec<&Element> = children
+        .iter()
+        .filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+        .collect();
+    let is_stretchy = |e: &Element| e.name == "mo" && e.attr("stretchy") == Some("true");
+
+    let mut boxes: Vec<Option<LayoutBox>> = elements
+        .iter()
+        .map(|&e| (!is_stretchy(e)).then(|| layout_element(e, style)))
+        .collect();
+
+    let axis = AXIS_HEIGHT * style.scale;
+    let half_height = boxes
+        .iter()
+        .flatten()
+        .map(|b| (b.ascent - axis).max(b.descent + axis))
+        .fold(0.0, f32::max);
+    let natural_half = (GLYPH_ASCENT + GLYPH_DESCENT) * style.scale / 2.0;
+    let stretch = (half_height / natural_half).max(1.0);
+    for (slot, element) in boxes.iter_mut().zip(&elements) {
+        if slot.is_none() {
+            *slot = Some(layout_operator(element, style, stretch));
+        }
+    }
+
+    let mut row = LayoutBox::default();
+    for (i, (layout, element)) in boxes.into_iter().flatten().zip(&elements).enumerate() {
+        let space = if element.name == "mo" && style.scale > 0.9 {
+            operator_space(&element.text(), i == 0)
+        } else {
+            (0.0, 0.0)
+        };
+        row.width += space.0 * style.scale;
+        row.ascent = row.ascent.max(layout.ascent);
+        row.descent = row.descent.max(layout.descent);
+        let (x, width) = (row.width, layout.width);
+        row.place(layout, x, 0.0);
+        row.width += width + space.1 * style.scale;
+    }
+    row
+}
+
+/// Space before and after an operator, in em: wide for relations, medium
+/// for binary operators (unless used as a prefix), trailing for punctuation.
+fn operator_space(op: &str, leading: bool) -> (f32, f32) {
+    const RELATIONS: &str = "=<>≤≥≠≈≡∼≃≅∝→←↔⇒⇐⇔⟶⟵⟷↦∈∉∋⊂⊆⊃⊇≪≫⊥∣≔";
+    const BINARY: &str = "+-−±∓×÷·⋅∘∗∪∩∧∨⊕⊗⊙";
+    let mut chars = op.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return (0.0, 0.0);
+    };
+    if RELATIONS.contains(c) {
+        (0.28, 0.28)
+    } else if BINARY.contains(c) && !leading {
+        (0.22, 0.22)
+    } else if c == ',' || c == ';' {
+        (0.0, 0.17)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+fn layout_identifier(element: &Element, style: Style) -> LayoutBox {
+    let text = element.text();
+    let single = text.chars().count() == 1;
+    match element.attr("mathvariant") {
+        Some("normal") => layout_text(&text, style, false, false),
+        Some("bold") => layout_text(&text, style, false, true),
+        Some("bold-italic") => layout_text(&text, style, true, true),
+        Some(variant @ ("double-struck" | "script" | "fraktur")) => {
+            let text: String = text
+                .chars()
+                .map(|c| math_alphanumeric(c, variant))
+                .collect();
+            layout_text(&text, style, false, false)
+        }
+        // Single letters are variables; longer names (sin, log) are upright
+        _ => layout_text(&text, style, single, false),
+    }
+}
+
+/// Maps a letter to its Unicode mathematical alphanumeric symbol.
+fn math_alphanumeric(c: char, variant: &str) -> char {
+    let (exceptions, upper, lower): (&[(char, char)], u32, Option<u32>) = match variant {
+        "double-struck" => (
+            &[
+                ('C', 'ℂ'),
+                ('H', 'ℍ'),
+                ('N', 'ℕ'),
+                ('P', 'ℙ'),
+                ('Q', 'ℚ'),
+                ('R', 'ℝ'),
+                ('Z', 'ℤ'),
+            ],
+            0x1D538,
+            Some(0x1D552),
+        ),
+        "script" => (
+            &[
+                ('B', 'ℬ'),
+                ('E', 'ℰ'),
+                ('F', 'ℱ'),
+                ('H', 'ℋ'),
+                ('I', 'ℐ'),
+                ('L', 'ℒ'),
+                ('M', 'ℳ'),
+                ('R', 'ℛ'),
+            ],
+            0x1D49C,
+            None,
+        ),
+        "fraktur" => (
+            &[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+            0x1D504,
+            Some(0x1D51E),
+        ),
+        _ => return c,
+    };
+    if let Some(&(_, mapped)) = exceptions.iter().find(|(from, _)| *from == c) {
+        return mapped;
+    }
+    let mapped = match c {
+        'A'..='Z' => char::from_u32(upper + (c as u32 - 'A' as u32)),
+        'a'..='z' => lower.and_then(|lower| char::from_u32(lower + (c as u32 - 'a' as u32))),
+        _ => None,
+    };
+    mapped.unwrap_or(c)
+}
+
+fn layout_text(text: &str, style: Style, italic: bool, bold: bool) -> LayoutBox {
+    let width: f32 = text.chars().map(char_width).sum::<f32>() * style.scale;
+    if text.trim().is_empty() {
+        return LayoutBox::empty(width);
+    }
+    LayoutBox {
+        width,
+        ascent: GLYPH_ASCENT * style.scale,
+        descent: GLYPH_DESCENT * style.scale,
+        items: vec![Item::Glyph {
+            x: width / 2.0,
+            y: 0.0,
+            text: text.to_string(),
+            scale: style.scale,
+            italic,
+            bold,
+            stretch: 1.0,
+        }],
+    }
+}
+
+fn layout_operator(element: &Element, style: Style, stretch: f32) -> LayoutBox {
+    let mut text = element.text();
+    if text == "-" {
+        // latex2mathml keeps the ASCII hyphen; typeset it as a minus sign
+        text = "−".to_string();
+    }
+    let large = text.chars().count() == 1 && "∑∏∐∫∬∭∮⋃⋂⨁⨂".contains(text.as_str());
+
+    if large && style.display {
+        // Large operators grow in display style and center on the axis
+        let style = Style {
+            scale: style.scale * 1.4,
+            ..style
+        };
+        let mut layout = layout_text(&text, style, false, false);
+        let shift =
+            AXIS_HEIGHT * style.scale / 1.4 - (GLYPH_ASCENT - GLYPH_DESCENT) * style.scale / 2.0;
+        for item in &mut layout.items {
+            item.translate(0.0, shift);
+        }
+        layout.ascent += shift;
+        layout.descent -= shift;
+        return layout;
+    }
+
+    let mut layout = layout_text(&text, style, false, false);
+    if stretch > 1.0 {
+        let axis = AXIS_HEIGHT * style.scale;
+        let half = (GLYPH_ASCENT + GLYPH_DESCENT) * style.scale / 2.0 * stretch;
+        for item in &mut layout.items {
+            if let Item::Glyph { stretch: s, .. } = item {
+                *s = stretch;
+            }
+        }
+        layout.ascent = axis + half;
+        layout.descent = half - axis;
+    }
+    layout
+}
+
+fn layout_scripts(
+    base: LayoutBox,
+    sub: Option<LayoutBox>,
+    sup: Option<LayoutBox>,
+    style: Style,
+) -> LayoutBox {
+    let s = style.scale;
+    let mut layout = LayoutBox {
+        width: base.width,
+        ascent: base.ascent,
+        descent: base.descent,
+        items: Vec::new(),
+    };
+    let (base_ascent, base_descent, x) = (base.ascent, base.descent, base.width + 0.03 * s);
+    layout.place(base, 0.0, 0.0);
+
+    let mut sup_y = (0.4 * s).max(base_ascent - 0.35 * s);
+    let mut sub_y = -(0.18 * s).max(base_descent - 0.1 * s);
+    if let (Some(sub), Some(sup)) = (&sub, &sup) {
+        // Keep a visible gap between the two scripts
+        let gap = (sup_y - sup.descent) - (sub_y + sub.ascent);
+        if gap < 0.1 * s {
+            let push = (0.1 * s - gap) / 2.0;
+            sup_y += push;
+            sub_y -= push;
+        }
+    }
+
+    let mut width = layout.width;
+    for (script, y) in [(sup, sup_y), (sub, sub_y)] {
+        if let Some(script) = script {
+            layout.ascent = layout.ascent.max(y + script.ascent);
+            layout.descent = layout.descent.max(script.descent - y);
+            width = width.max(x + script.width);
+            layout.place(script, x, y);
+        }
+    }
+    layout.width = width + 0.03 * s;
+    layout
+}
+
+fn layout_under_over(element: &Element, children: &[&Element], style: Style) -> LayoutBox {
+    let s = style.scale;
+    let Some(base) = children.first() else {
+        return LayoutBox::default();
+    };
+    let (under, over) = match element.name.as_str() {
+        "munder" => (children.get(1), None),
+        "mover" => (None, children.get(1)),
+        _ => (children.get(1), children.get(2)),
+    };
+    let base = layout_element(base, style);
+
+    let mut layout = LayoutBox {
+        ascent: base.ascent,
+        descent: base.descent,
+        ..LayoutBox::default()
+    };
+    let accent = |e: &&Element| e.name == "mo" && e.attr("accent") == Some("true");
+    let scripts: Vec<(LayoutBox, bool, bool)> = [(over, true), (under, false)]
+        .into_iter()
+        .filter_map(|(script, is_over)| {
+            let script = script?;
+            let is_accent = accent(script);
+            let style = if is_accent { style } else { style.script() };
+            let layout = layout_accent_or_script(script, style, is_accent, base.width);
+            Some((layout, is_over, is_accent))
+        })
+        .collect();
+
+    let width = scripts
+        .iter()
+        .map(|(b, ..)| b.width)
+        .fold(base.width, f32::max);
+    let (base_ascent, base_descent) = (base.ascent, base.descent);
+    layout.place_centered(base, width, 0.0);
+
+    for (script, is_over, is_accent) in scripts {
+        let gap = if is_accent { 0.0 } else { 0.1 * s };
+        let y = if is_over {
+            base_ascent + gap + script.descent
+        } else {
+            -(base_descent + gap + script.ascent)
+        };
+        layout.place_centered(script, width, y);
+    }
+    layout.width = width;
+    layout
+}
+
+/// Bars over or under a base are drawn as rules (`\overline` spanning the
+/// whole base, `\bar` short), and accent glyphs are lowered to sit right on
+/// top of it; any other script is laid out as is.
+fn layout_accent_or_script(
+    element: &Element,
+    style: Style,
+    is_accent: bool,
+    base_width: f32,
+) -> LayoutBox {
+    let s = style.scale;
+    let text = element.text();
+    if is_accent && matches!(text.as_str(), "¯" | "‾" | "_") {
+        let width = if text == "¯" { 0.5 * s } else { base_width };
+        return LayoutBox {
+            width,
+            ascent: 0.08 * s,
+            descent: -0.02 * s,
+            items: vec![Item::Rule {
+                x: 0.0,
+                y: 0.03 * s,
+                width,
+                thickness: RULE_THICKNESS * s,
+            }],
+        };
+    }
+
+    let mut layout = layout_element(element, style);
+    if is_accent {
+        // Accent glyphs sit high in their em box; pull them down onto the base
+        let drop = if "→⃗←↔".contains(text.as_str()) {
+            0.2 * s
+        } else {
+            0.45 * s
+        };
+        for item in &mut layout.items {
+            item.translate(0.0, -drop);
+        }
+        layout.ascent -= drop;
+        layout.descent = 0.0;
+    }
+    layout
+}
+
+fn layout_fraction(
+    numerator: LayoutBox,
+    denominator: LayoutBox,
+    thickness: f32,
+    style: Style,
+) -> LayoutBox {
+    let s = style.scale;
+    let axis = AXIS_HEIGHT * s;
+    let gap = 0.12 * s;
+    let thickness = thickness * s;
+    let width = numerator.width.max(denominator.width) + 0.2 * s;
+
+    let mut layout = LayoutBox {
+        width,
+        ..LayoutBox::default()
+    };
+    let numerator_y = axis + thickness / 2.0 + gap + numerator.descent;
+    let denominator_y = axis - thickness / 2.0 - gap - denominator.ascent;
+    layout.place_centered(numerator, width, numerator_y);
+    layout.place_centered(denominator, width, denominator_y);
+    if thickness > 0.0 {
+        layout.items.push(Item::Rule {
+            x: 0.05 * s,
+            y: axis,
+            width: width - 0.1 * s,
+            thickness,
+        });
+    }
+    layout.width = width + 0.1 * s;
+    for item in &mut layout.items {
+        item.translate(0.05 * s, 0.0);
+    }
+    layout
+}
+
+fn layout_radical(content: LayoutBox, index: Option<LayoutBox>, style: Style) -> LayoutBox {
+    let s = style.scale;
+    let thickness = RULE_THICKNESS * s;
+    let top = content.ascent.max(GLYPH_ASCENT * s) + 0.12 * s;
+    let bottom = -content.descent.max(GLYPH_DESCENT * s) - 0.05 * s;
+    let tick_y = bottom + (top - bottom) * 0.45;
+
+    // Room for the index above the radical's tick
+    let offset = index
+        .as_ref()
+        .map_or(0.0, |index| (index.width - 0.2 * s).max(0.0));
+    let sign_width = 0.55 * s;
+    let bar_end = offset + sign_width + content.width + 0.1 * s;
+
+    let mut layout = LayoutBox {
+        width: bar_end + 0.05 * s,
+        ascent: top + thickness,
+        descent: -bottom,
+        items: vec![Item::Polyline {
+            points: vec![
+                (offset, tick_y),
+                (offset + 0.12 * s, tick_y + 0.06 * s),
+                (offset + 0.28 * s, bottom),
+                (offset + 0.5 * s, top),
+                (bar_end, top),
+            ],
+            thickness,
+        }],
+    };
+    if let Some(index) = index {
+        let y = tick_y + 0.1 * s + index.descent;
+        layout.ascent = layout.ascent.max(y + index.ascent);
+        layout.place(index, 0.0, y);
+    }
+    layout.place(content, offset + sign_width, 0.0);
+    layout
+}
+
+fn layout_table(element: &Element, style: Style) -> LayoutBox {
+    let s = style.scale;
+    let cell_style = Style {
+        display: false,
+        ..style
+    };
+    let rows: Vec<Vec<LayoutBox>> = element
+        .elements()
+        .filter(|row| row.name == "mtr")
+        .map(|row| {
+            row.elements()
+                .map(|cell| layout_row(&cell.children, cell_style))
+                .collect()
+        })
+        .filter(|cells: &Vec<LayoutBox>| !cells.is_empty())
+        .collect();
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let column_widths: Vec<f32> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .filter_map(|row| row.get(c))
+                .map(|cell| cell.width)
+                .fold(0.0, f32::max)
+        })
+        .collect();
+    let column_gap = 0.8 * s;
+    let row_gap = 0.25 * s;
+
+    let mut layout = LayoutBox::default();
+    let mut y = 0.0;
+    for row in rows {
+        let ascent = row.iter().map(|c| c.ascent).fold(0.0, f32::max);
+        let descent = row.iter().map(|c| c.descent).fold(0.0, f32::max);
+        y -= ascent;
+        let mut x = 0.0;
+        for (cell, &width) in row.into_iter().zip(&column_widths) {
+            let cell_x = x + (width - cell.width) / 2.0;
+            layout.place(cell, cell_x, y);
+            x += width + column_gap;
+        }
+        y -= descent + row_gap;
+    }
+    let height = -(y + row_gap);
+
+    // Center the table on the math axis
+    let shift = height / 2.0 + AXIS_HEIGHT * s;
+    for item in &mut layout.items {
+        item.translate(0.0, shift);
+    }
+    layout.width = column_widths.iter().sum::<f32>()
+        + column_gap * column_widths.len().saturating_sub(1) as f32;
+    layout.ascent = shift;
+    layout.descent = height - shift;
+    layout
+}
+
+/// Estimated advance width of `c` in em, for a serif math font.
+fn char_width(c: char) -> f32 {
+    match c {
+        // Combining marks and invisible operators take no space
+        '\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}' | '\u{2061}'..='\u{2064}' => 0.0,
+        'i' | 'j' | 'l' | '\'' | '′' => 0.3,
+        'f' | 't' | 'r' => 0.38,
+        'm' | 'w' => 0.75,
+        'M' | 'W' => 0.9,
+        'a'..='z' => 0.52,
+        'A'..='Z' => 0.7,
+        '0'..='9' => 0.5,
+        ' ' => 0.25,
+        '.' | ',' | ':' | ';' | '!' => 0.28,
+        '(' | ')' | '[' | ']' | '{' | '}' | '|' | '⟨' | '⟩' => 0.36,
+        '‖' => 0.5,
+        'α'..='ω' => 0.56,
+        'Α'..='Ω' => 0.72,
+        '∑' | '∏' | '∐' => 0.95,
+        '∫' | '∬' | '∭' | '∮' => 0.5,
+        '∂' | 'ℏ' | '∇' => 0.56,
+        '→' | '←' | '↔' | '⇒' | '⇐' | '⇔' | '↦' => 1.0,
+        '⟶' | '⟵' | '⟷' => 1.6,
+        '·' | '⋅' => 0.28,
+        _ if c.is_ascii_punctuation() => 0.6,
+        _ => 0.78,
+    }
+}
+
+fn write_svg(layout: &LayoutBox) -> MathImage {
+    let width = ((layout.width + 2.0 * PADDING) * FONT_SIZE).ceil();
+    let height = ((layout.ascent + layout.descent + 2.0 * PADDING) * FONT_SIZE).ceil();
+    let baseline = (layout.ascent + PADDING) * FONT_SIZE;
+    // Maps layout coordinates (em, y up) to SVG pixels (y down)
+    let px = |x: f32| (x + PADDING) * FONT_SIZE;
+    let py = |y: f32| baseline - y * FONT_SIZE;
+
+    let mut svg = format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            "<style>svg{{color:#1a1a1a}}@media (prefers-color-scheme:dark){{svg{{color:#e6e6e6}}}}</style>",
+            r#"<g fill="currentColor" font-family="{font}" text-anchor="middle">"#
+        ),
+        w = width,
+        h = height,
+        font = FONT_FAMILY
+    );
+
+    for item in &layout.items {
+        match item {
+            Item::Glyph {
+                x,
+                y,
+                text,
+                scale,
+                italic,
+                bold,
+                stretch,
+            } => {
+                let mut attributes = format!(r#" font-size="{:.1}""#, scale * FONT_SIZE);
+                if *italic {
+                    attributes.push_str(r#" font-style="italic""#);
+                }
+                if *bold {
+                    attributes.push_str(r#" font-weight="bold""#);
+                }
+                let text = escape(text);
+                if *stretch > 1.0 {
+                    // Scale around the axis, where stretchy fences are centered
+                    let axis = y + AXIS_HEIGHT * scale;
+                    svg.push_str(&format!(
+                        r#"<text transform="translate({:.1} {:.1}) scale(1 {:.3})" y="{:.1}"{}>{}</text>"#,
+                        px(*x),
+                        py(axis),
+                        stretch,
+                        AXIS_HEIGHT * scale * FONT_SIZE,
+                        attributes,
+                        text
+                    ));
+                } else {
+                    svg.push_str(&format!(
+                        r#"<text x="{:.1}" y="{:.1}"{}>{}</text>"#,
+                        px(*x),
+                        py(*y),
+                        attributes,
+                        text
+                    ));
+                }
+            }
+            Item::Rule {
+                x,
+                y,
+                width,
+                thickness,
+            } => svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.2}"/>"#,
+                px(*x),
+                py(*y + thickness / 2.0),
+                width * FONT_SIZE,
+                thickness * FONT_SIZE
+            )),
+            Item::Polyline { points, thickness } => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|&(x, y)| format!("{:.1},{:.1}", px(x), py(y)))
+                    .collect();
+                svg.push_str(&format!(
+                    r#"<polyline points="{}" fill="none" stroke="currentColor" stroke-width="{:.2}" stroke-linejoin="round"/>"#,
+                    points.join(" "),
+                    thickness * FONT_SIZE
+                ));
+            }
+        }
+    }
+    svg.push_str("</g></svg>");
+
+    MathImage {
+        svg,
+        width: width as u32,
+        height: height as u32,
+        depth: height - baseline,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entities, mathml_to_svg, parse};
+    use crate::math::to_mathml;
+    use latex2mathml::DisplayStyle;
+
+    #[test]
+    fn parses_latex2mathml_quirks() {
+        let math = parse(
+            r#"<math display="inline"><mo><</mo><mrow><mo maxsize="1.2em">(</mro></mrow><mi>x</mi></math>"#,
+        )
+        .unwrap();
+        let names: Vec<&str> = math.elements().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["mo", "mrow", "mi"]);
+        assert_eq!(math.text(), "<(x");
+        assert_eq!(
+            decode_entities("&rang;&#x2061;&#8722;&bogus;"),
+            "⟩\u{2061}−&bogus;"
+        );
+    }
+
+    #[test]
+    fn fraction_is_taller_than_plain_text() {
+        let render = |latex: &str, style| mathml_to_svg(&to_mathml(latex, style).unwrap()).unwrap();
+        let plain = render("x+1", DisplayStyle::Inline);
+        let fraction = render(r"\frac{x+1}{2}", DisplayStyle::Block);
+
+        assert!(fraction.height > plain.height);
+        assert!(fraction.svg.contains("<rect"));
+        assert!(plain.svg.contains(r#"font-style="italic">x</text>"#));
+        assert!(plain.depth > 0.0 && plain.depth < plain.height as f32 / 2.0);
+    }
+
+    #[test]
+    fn radicals_scripts_and_fences_render() {
+        let mathml = to_mathml(
+            r"\left(\sqrt{a^2+b_1}\right) \leq \mathbb{R}",
+            DisplayStyle::Inline,
+        )
+        .unwrap();
+        let image = mathml_to_svg(&mathml).unwrap();
+
+        assert!(image.svg.contains("<polyline"));
+        assert!(image.svg.contains("scale(1 "));
+        assert!(image.svg.contains(">ℝ</text>"));
+        assert!(image.svg.contains(">≤</text>"));
+    }
+}
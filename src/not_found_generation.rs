@@ -0,0 +1,82 @@
+//! Generates `404.html`, the page GitHub Pages serves for any missing URL.
+//!
+//! Besides a search form and the newest posts, the page carries every post's
+//! URL, title and slugs (its id and aliases), so an inline script can
+//! suggest the posts whose slug is closest to the requested path.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tera::Tera;
+
+use crate::fingerprinting;
+use crate::optimization;
+use crate::page_generation::{format_date, Post};
+use crate::urls::SiteUrls;
+
+/// Newest posts listed on the page.
+const RECENT_POSTS: usize = 5;
+
+#[derive(Serialize)]
+struct RecentPost<'a> {
+    url: String,
+    title: &'a str,
+    date: String,
+}
+
+/// A post as the suggestion script sees it, with one-letter keys to keep
+/// the inline list small.
+#[derive(Serialize)]
+struct SlugEntry<'a> {
+    u: String,
+    t: &'a str,
+    /// Id first, then aliases.
+    s: Vec<&'a str>,
+}
+
+pub fn build_not_found_page(out_dir: &Path, posts: &[Post], urls: &SiteUrls) -> Result<()> {
+    let start = Instant::now();
+
+    let mut tera = Tera::new("website/not-found/**/*.html")?;
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
+
+    let recent_posts: Vec<RecentPost> = posts
+        .iter()
+        .take(RECENT_POSTS)
+        .map(|post| RecentPost {
+            url: urls.post(&post.id),
+            title: &post.title,
+            date: format_date(&post.date),
+        })
+        .collect();
+    let slugs: Vec<SlugEntry> = posts
+        .iter()
+        .map(|post| SlugEntry {
+            u: urls.post(&post.id),
+            t: &post.title,
+            s: std::iter::once(post.id.as_str())
+                .chain(post.aliases.iter().map(String::as_str))
+                .collect(),
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("recent_posts", &recent_posts);
+    // Safe inside <script>: JSON never needs a literal "</"
+    context.insert(
+        "slugs_json",
+        &serde_json::to_string(&slugs)?.replace("</", "<\\/"),
+    );
+
+    fs::write(out_dir.join("404.html"), tera.render("404.html", &context)?)?;
+
+    println!(
+        "✓ Generated 404.html in {:.2}s",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
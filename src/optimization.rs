@@ -1,4 +1,6 @@
 use crate::class_minifier::minify_css_classes;
+use crate::image_optimization::{self, ImageReport};
+use crate::social_card_generation::CARD_DIR;
 use anyhow::Result;
 use lightningcss::stylesheet::{ParserOptions, PrinterOptions, StyleSheet};
 use minify_html::{minify, Cfg};
@@ -6,14 +8,20 @@ use minify_js::minify as minify_js_code;
 use minify_js::{Session, TopLevelMode};
 use rayon::prelude::*;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
+use tera::Value;
 
-pub fn optimize_website_assets(out_dir: &Path) -> Result<()> {
+/// Directories of `website/` whose CSS and JS files are published under
+/// `/styles/` and `/js/`.
+pub const STATIC_ASSET_DIRS: &[&str] = &["index", "post", "global", "search", "map", "not-found"];
+
+pub fn optimize_website_assets(out_dir: &Path, cache_dir: &Path) -> Result<()> {
     copy_static_assets(out_dir)?;
-    optimize_assets(out_dir)?;
+    optimize_assets(out_dir, cache_dir)?;
 
     Ok(())
 }
@@ -47,8 +55,8 @@ pub fn copy_static_assets(out_dir: &Path) -> Result<()> {
     let js_dest = out_dir.join("js");
     fs::create_dir_all(&js_dest)?;
 
-    // Copy CSS and JS files from index/, post/, global/
-    for dir_name in &["index", "post", "global"] {
+    // Copy CSS and JS files from index/, post/, global/, search/, map/, not-found/
+    for dir_name in STATIC_ASSET_DIRS {
         let src_dir = Path::new("website").join(dir_name);
         if src_dir.exists() {
             for entry in fs::read_dir(src_dir)? {
@@ -90,7 +98,7 @@ fn copy_dir_recursive(src: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn optimize_assets(out_dir: &Path) -> Result<()> {
+pub fn optimize_assets(out_dir: &Path, cache_dir: &Path) -> Result<()> {
     let start = Instant::now();
     // Collect all files to optimize
     let mut css_files = Vec::new();
@@ -105,6 +113,8 @@ pub fn optimize_assets(out_dir: &Path) -> Result<()> {
         &mut html_files,
         &mut image_files,
     )?;
+    // Social cards are rendered without metadata at their final size and cached on their own
+    image_files.retain(|path| !path.starts_with(out_dir.join(CARD_DIR)));
 
     let total_files = css_files.len() + js_files.len() + html_files.len() + image_files.len();
 
@@ -118,13 +128,13 @@ pub fn optimize_assets(out_dir: &Path) -> Result<()> {
 
     // Optimize in phases:
     // 0. Minify CSS class names across all files
-    // 1. Minify CSS and JS files (in parallel)
+    // 1. Minify CSS and JS files and optimize images (in parallel)
     // 2. Inline CSS into HTML files (requires minified CSS)
     // 3. Minify HTML files (after CSS inlining)
     let css_count = AtomicUsize::new(0);
     let js_count = AtomicUsize::new(0);
     let html_count = AtomicUsize::new(0);
-    let img_count = AtomicUsize::new(0);
+    let mut image_report = ImageReport::default();
     let inline_count = AtomicUsize::new(0);
 
     // Phase 0: Minify CSS class names (must happen before CSS/JS minification)
@@ -149,19 +159,23 @@ pub fn optimize_assets(out_dir: &Path) -> Result<()> {
         });
 
         s.spawn(|_| {
-            image_files.par_iter().for_each(|path| {
-                if optimize_image_file(path).is_ok() {
-                    img_count.fetch_add(1, Ordering::Relaxed);
-                }
-            });
+            image_report = image_optimization::optimize_images(&image_files, cache_dir);
         });
     });
 
-    // Phase 2: Inline CSS into HTML files (now that CSS is minified)
+    // Phase 2: Inline CSS into HTML files (now that CSS is minified), and
+    // offer the modern-format siblings written in phase 1
     html_files.par_iter().for_each(|path| {
         if inline_css_in_html_file(out_dir, path).is_ok() {
             inline_count.fetch_add(1, Ordering::Relaxed);
         }
+        if let Err(e) = add_picture_sources_to_html_file(out_dir, path) {
+            eprintln!(
+                "Warning: Could not add picture sources to {}: {}",
+                path.display(),
+                e
+            );
+        }
     });
 
     // Phase 3: Minify HTML files (after CSS has been inlined)
@@ -197,6 +211,15 @@ pub fn optimize_assets(out_dir: &Path) -> Result<()> {
         after_size as f64 / 1024.0,
         percent_saved
     );
+    println!(
+        "  ({} images, {} cached, {} failed; added {} narrower copies, {} WebP and {} AVIF versions)",
+        image_report.optimized,
+        image_report.cached,
+        image_report.failed,
+        image_report.variants,
+        image_report.webp,
+        image_report.avif
+    );
     Ok(())
 }
 
@@ -250,7 +273,11 @@ fn minify_css_file(path: &Path) -> Result<()> {
 
 fn minify_js_file(path: &Path) -> Result<()> {
     let js = fs::read_to_string(path)?;
+    fs::write(path, minify_js_source(&js)?.as_bytes())?;
+    Ok(())
+}
 
+fn minify_js_source(js: &str) -> Result<String> {
     // Use minify-js for proper JS minification
     let session = Session::new();
     let mut output = Vec::new();
@@ -258,7 +285,7 @@ fn minify_js_file(path: &Path) -> Result<()> {
     minify_js_code(&session, TopLevelMode::Global, js.as_bytes(), &mut output)
         .map_err(|e| anyhow::anyhow!("JS minify error: {:?}", e))?;
 
-    let mut minified = String::from_utf8(output).unwrap_or_else(|_| js.clone());
+    let mut minified = String::from_utf8(output).unwrap_or_else(|_| js.to_string());
 
     // Fix minify-js bug: it over-escapes newlines in string literals
     // Replace `\\n` with `\n` in string contexts
@@ -267,8 +294,7 @@ fn minify_js_file(path: &Path) -> Result<()> {
     minified = minified.replace(r#"split('\\n')"#, r#"split('\n')"#);
     minified = minified.replace(r#"split("\\n")"#, r#"split("\n")"#);
 
-    fs::write(path, minified.as_bytes())?;
-    Ok(())
+    Ok(minified)
 }
 
 fn minify_html_file(path: &Path) -> Result<()> {
@@ -289,12 +315,26 @@ fn minify_html_file(path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn optimize_image_file(_path: &Path) -> Result<()> {
-    // Images are already optimized at the source level using Sharp.
-    // No need to re-optimize during build - just copy them as-is.
+fn add_picture_sources_to_html_file(out_dir: &Path, path: &Path) -> Result<()> {
+    let html = fs::read_to_string(path)?;
+    let result = image_optimization::add_picture_sources(&html, out_dir);
+    if result != html {
+        fs::write(path, result)?;
+    }
     Ok(())
 }
 
+/// Tera function that outputs a placeholder for CSS inlining.
+/// Usage in template: {{ inline_css(path="/styles/global.css") }}
+/// The placeholder is replaced with actual CSS content during optimization.
+pub fn inline_css_placeholder(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let path = args
+        .get("path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| tera::Error::msg("inline_css requires a 'path' argument"))?;
+    Ok(Value::String(format!("<!-- INLINE_CSS:{} -->", path)))
+}
+
 /// Inline CSS files into HTML by replacing <!-- INLINE_CSS:/path/to/file.css --> placeholders.
 /// This should be called AFTER CSS minification so the inlined CSS is already minified.
 fn inline_css_in_html_file(out_dir: &Path, html_path: &Path) -> Result<()> {
@@ -323,3 +363,31 @@ fn inline_css_in_html_file(out_dir: &Path, html_path: &Path) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn js_minification_is_deterministic() {
+        let js = r#"
+            (function () {
+                function first() { return document.querySelector("a"); }
+                function second() { return document.querySelector("b"); }
+                function third() { return document.querySelector("c"); }
+                const a = "shared-a", b = "shared-b";
+                console.log("shared-a", "shared-a", "shared-b", "shared-b", 42, 42);
+                first(); second(); third();
+            })();
+        "#;
+        let expected = minify_js_source(js).unwrap();
+        for _ in 0..20 {
+            assert_eq!(minify_js_source(js).unwrap(), expected);
+        }
+        // Hoisted function declarations keep their source order
+        let first = expected.find("`a`").unwrap();
+        let second = expected.find("`b`").unwrap();
+        let third = expected.find("`c`").unwrap();
+        assert!(first < second && second < third, "{}", expected);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::image_optimization;
 use crate::math;
+use crate::urls::{tag_url, SiteUrls};
 use anyhow::Result;
 use chrono::NaiveDate;
 use gix::object::tree::diff::{Action, Change};
@@ -50,6 +52,16 @@ static ID_ATTR_RE: Lazy<Regex> =
 static ENTITY_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"&(#(?:x[0-9a-fA-F]+|\d+)|[a-zA-Z]+);").unwrap());
 static HTML_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+static NON_CONTENT_BLOCK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<(style|script)\b[^>]*>.*?</(?:style|script)\s*>").unwrap());
+static CODE_BLOCK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<pre\b.*?</pre\s*>").unwrap());
+static MATH_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<math\b.*?</math\s*>").unwrap());
+static HASHTAG_PARAGRAPH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)<p class="post-hashtags">.*?</p>"#).unwrap());
+static SECTION_HEADING_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<h[1-6]\b[^>]*>(.*?)</h[1-6]\s*>").unwrap());
+static PARAGRAPH_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)(<p\b[^>]*>)(.*?)</p>").unwrap());
+static BARE_URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://\S+").unwrap());
 static URL_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(https?://[^\s<>]+?)([.,;:!?)]*(?:\s|$))").unwrap());
 
@@ -59,6 +71,14 @@ pub struct PostMetadata {
     pub icon: Option<String>,
     pub title: Option<String>,
     pub tags: Option<Vec<String>>,
+    /// Ids of posts to pin at the top of the related posts list.
+    pub related: Option<Vec<String>>,
+    /// Cover image shown in social previews instead of the generated card.
+    pub image: Option<String>,
+    /// Keeps the post out of search engines, the sitemap and `llms.txt`.
+    pub noindex: Option<bool>,
+    /// Former ids of the post, suggested on the 404 page for requests to them.
+    pub aliases: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -70,8 +90,10 @@ pub struct Post {
     pub tags: Vec<String>,
     pub icon: Option<String>,
     pub content_html: String,
-    #[cfg_attr(not(feature = "smart-similar"), allow(dead_code))]
-    pub content_raw: String,
+    pub related: Vec<String>,
+    pub image: Option<String>,
+    pub noindex: bool,
+    pub aliases: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -81,13 +103,19 @@ pub struct PostSummary {
     pub date: String,
     pub tags: Vec<String>,
     pub icon: Option<String>,
+    /// Similarity to the post this summary is listed under, when it was scored.
+    pub score: Option<f32>,
 }
 
-pub fn get_posts_data(_out_dir: &Path) -> Result<Arc<Vec<Post>>> {
+pub fn get_posts_data(urls: &SiteUrls) -> Result<Arc<Vec<Post>>> {
     let start = Instant::now();
     let posts_dir = Path::new("posts");
     let mut posts = read_all_posts(posts_dir)?;
-    posts.sort_by(|a, b| b.date.cmp(&a.date));
+    for post in &mut posts {
+        post.content_html = urls.canonicalize_links(&post.content_html);
+    }
+    // Ties on the date are broken by id, so the order never depends on the file system
+    posts.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.id.cmp(&b.id)));
     println!(
         "✓ Loaded {} posts in {:.2}s",
         posts.len(),
@@ -97,7 +125,7 @@ pub fn get_posts_data(_out_dir: &Path) -> Result<Arc<Vec<Post>>> {
 }
 
 pub fn read_all_posts(posts_dir: &Path) -> Result<Vec<Post>> {
-    let entries: Vec<_> = fs::read_dir(posts_dir)?
+    let mut entries: Vec<_> = fs::read_dir(posts_dir)?
         .filter_map(|e| e.ok())
         .filter(|e| {
             matches!(
@@ -107,6 +135,8 @@ pub fn read_all_posts(posts_dir: &Path) -> Result<Vec<Post>> {
         })
         .map(|e| e.path())
         .collect();
+    // Directory listing order is file-system dependent
+    entries.sort();
 
     let git_dates = get_all_git_dates(&entries);
 
@@ -122,19 +152,18 @@ pub fn read_all_posts(posts_dir: &Path) -> Result<Vec<Post>> {
             let date = metadata.date.or(git_first)?;
             let date_modified = git_last.unwrap_or_else(|| date.clone());
 
-            let (title, tags, content_html, content_raw) = if is_html {
+            let (title, tags, content_html) = if is_html {
                 let title = metadata
                     .title
                     .unwrap_or_else(|| extract_html_title(&body).unwrap_or_default());
                 let tags = metadata.tags.unwrap_or_default();
-                (title, tags, body.clone(), body)
+                (title, tags, body)
             } else {
                 let title = metadata.title.unwrap_or_else(|| extract_title(&body));
                 let tags = metadata.tags.unwrap_or_else(|| extract_tags(&content));
-                let content_raw = body.clone();
                 let markdown_without_title = remove_first_h1(&body);
                 let content_html = markdown_to_html(&markdown_without_title, &tags);
-                (title, tags, content_html, content_raw)
+                (title, tags, content_html)
             };
 
             if title.is_empty() {
@@ -156,7 +185,10 @@ pub fn read_all_posts(posts_dir: &Path) -> Result<Vec<Post>> {
                     }
                 }),
                 content_html,
-                content_raw,
+                related: metadata.related.unwrap_or_default(),
+                image: metadata.image.filter(|image| !image.trim().is_empty()),
+                noindex: metadata.noindex.unwrap_or(false),
+                aliases: metadata.aliases.unwrap_or_default(),
             })
         })
         .collect();
@@ -165,9 +197,7 @@ pub fn read_all_posts(posts_dir: &Path) -> Result<Vec<Post>> {
 }
 
 fn parse_frontmatter(content: &str) -> (PostMetadata, String) {
-    if let Some(caps) = FRONTMATTER_RE.captures(content) {
-        let yaml = caps.get(1).unwrap().as_str();
-        let markdown = caps.get(2).unwrap().as_str();
+    if let Some((yaml, markdown)) = split_frontmatter(content) {
         let metadata: PostMetadata = serde_yaml::from_str(yaml).unwrap_or_default();
         (metadata, markdown.to_string())
     } else {
@@ -175,6 +205,12 @@ fn parse_frontmatter(content: &str) -> (PostMetadata, String) {
     }
 }
 
+/// Splits a post source into its YAML frontmatter and the body after it.
+pub fn split_frontmatter(content: &str) -> Option<(&str, &str)> {
+    let caps = FRONTMATTER_RE.captures(content)?;
+    Some((caps.get(1)?.as_str(), caps.get(2)?.as_str()))
+}
+
 fn ts_to_date(ts: i64, offset: i32) -> Option<String> {
     let local_ts = ts + offset as i64;
     chrono::DateTime::from_timestamp(local_ts, 0).map(|dt| dt.format("%Y-%m-%d").to_string())
@@ -316,7 +352,7 @@ fn extract_tags(content: &str) -> Vec<String> {
     Vec::new()
 }
 
-fn remove_first_h1(markdown: &str) -> String {
+pub fn remove_first_h1(markdown: &str) -> String {
     if let Some(first_line) = markdown.lines().next() {
         if first_line.starts_with("# ") {
             let mut rest = &markdown[first_line.len()..];
@@ -379,6 +415,8 @@ fn markdown_to_html(markdown: &str, tags: &[String]) -> String {
     let mut in_image = false;
     let mut image_alt_text = String::new();
     let mut image_url = String::new();
+    let mut image_title = String::new();
+    let mut image_count = 0;
     let mut in_link = false;
 
     for event in parser {
@@ -493,21 +531,26 @@ fn markdown_to_html(markdown: &str, tags: &[String]) -> String {
                     html_output.push_str(&linkify_bare_urls(&text));
                 }
             }
-            Event::Start(Tag::Image { dest_url, .. }) => {
+            Event::Start(Tag::Image {
+                dest_url, title, ..
+            }) => {
                 in_image = true;
                 image_alt_text.clear();
                 image_url = dest_url.to_string();
+                image_title = title.to_string();
             }
             Event::End(TagEnd::Image) => {
-                let mut escaped_alt = String::new();
-                escape_html(&mut escaped_alt, &image_alt_text).unwrap();
-                html_output.push_str(&format!(
-                    r#"<img src="{}" alt="{}">"#,
-                    image_url, escaped_alt
+                html_output.push_str(&render_image(
+                    &image_url,
+                    &image_alt_text,
+                    &image_title,
+                    image_count == 0,
                 ));
+                image_count += 1;
                 in_image = false;
                 image_alt_text.clear();
                 image_url.clear();
+                image_title.clear();
             }
             Event::Code(code) => {
                 let mut escaped = String::new();
@@ -613,6 +656,8 @@ fn markdown_to_html(markdown: &str, tags: &[String]) -> String {
         }
     }
 
+    html_output = unwrap_figures(&html_output);
+
     // Add IDs to headings
     html_output = add_heading_ids(&html_output);
 
@@ -622,6 +667,67 @@ fn markdown_to_html(markdown: &str, tags: &[String]) -> String {
     html_output
 }
 
+/// `sizes` of post images: full width on narrow screens, else the width
+/// of the article column.
+const IMAGE_SIZES: &str = "(max-width: 768px) 100vw, 644px";
+
+/// `<img>` of a markdown image, with its size and narrower copies when the
+/// image is ours, and wrapped in a `<figure>` captioned with its title.
+/// Images after the first are loaded lazily.
+fn render_image(url: &str, alt: &str, title: &str, first: bool) -> String {
+    let mut img = String::from(r#"<img src=""#);
+    escape_html(&mut img, url).unwrap();
+    img.push_str(r#"" alt=""#);
+    escape_html(&mut img, alt).unwrap();
+    img.push('"');
+    if let Some(image) = image_optimization::responsive_image(url) {
+        img.push_str(&format!(
+            r#" width="{}" height="{}""#,
+            image.width, image.height
+        ));
+        if let Some(srcset) = image.srcset {
+            img.push_str(&format!(r#" srcset="{}" sizes="{}""#, srcset, IMAGE_SIZES));
+        }
+    }
+    if !first {
+        img.push_str(r#" loading="lazy" decoding="async""#);
+    }
+    img.push('>');
+
+    if title.is_empty() {
+        return img;
+    }
+    let mut figure = format!("<figure>{}<figcaption>", img);
+    escape_html(&mut figure, title).unwrap();
+    figure.push_str("</figcaption></figure>");
+    figure
+}
+
+/// A figure cannot sit inside a paragraph: a paragraph holding only a
+/// figure is replaced by it, and figures among text become block spans.
+fn unwrap_figures(html: &str) -> String {
+    PARAGRAPH_RE
+        .replace_all(html, |caps: &regex::Captures| {
+            let content = &caps[2];
+            if !content.contains("<figure>") {
+                return caps[0].to_string();
+            }
+            let lone_figure = content.starts_with("<figure>")
+                && content.ends_with("</figure>")
+                && content.matches("<figure>").count() == 1;
+            if lone_figure {
+                return content.to_string();
+            }
+            let inline = content
+                .replace("<figure>", r#"<span class="figure">"#)
+                .replace("<figcaption>", r#"<span class="figcaption">"#)
+                .replace("</figcaption>", "</span>")
+                .replace("</figure>", "</span>");
+            format!("{}{}</p>", &caps[1], inline)
+        })
+        .into_owned()
+}
+
 fn convert_syntect_classes_to_prism(html: &str) -> String {
     // Map syntect scope classes to Prism token classes
     // Syntect uses TextMate scope names, we need to convert to Prism's simpler classes
@@ -720,36 +826,19 @@ pub fn extract_headings(html: &str) -> Vec<Heading> {
         .collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::extract_headings;
-
-    #[test]
-    fn extract_headings_preserves_explicit_ids() {
-        let headings =
-            extract_headings(r#"<h2 id="outlook">Outlook: why this trick stops at the plane</h2>"#);
-
-        assert_eq!(headings.len(), 1);
-        assert_eq!(headings[0].id, "outlook");
-    }
-
-    #[test]
-    fn extract_headings_generates_ids_when_missing() {
-        let headings = extract_headings("<h2>Why this trick works</h2>");
-
-        assert_eq!(headings.len(), 1);
-        assert_eq!(headings[0].id, "why-this-trick-works");
-    }
-}
-
 fn decode_html_entities(text: &str) -> String {
     ENTITY_RE
         .replace_all(text, |caps: &regex::Captures| {
             let code = &caps[1];
-            if code.starts_with('#') {
-                let is_hex = code.starts_with("#x") || code.starts_with("#X");
-                let num_str = if is_hex { &code[2..] } else { &code[1..] };
-                if let Ok(num) = u32::from_str_radix(num_str, if is_hex { 16 } else { 10 }) {
+            if let Some(numeric) = code.strip_prefix('#') {
+                let (num_str, radix) = match numeric
+                    .strip_prefix('x')
+                    .or_else(|| numeric.strip_prefix('X'))
+                {
+                    Some(hex) => (hex, 16),
+                    None => (numeric, 10),
+                };
+                if let Ok(num) = u32::from_str_radix(num_str, radix) {
                     if let Some(ch) = char::from_u32(num) {
                         return ch.to_string();
                     }
@@ -774,6 +863,61 @@ pub fn strip_html_tags(html: &str) -> String {
     HTML_TAG_RE.replace_all(html, "").to_string()
 }
 
+/// Reduces rendered HTML to whitespace-normalized plain text, dropping
+/// `<style>`/`<script>` bodies and decoding entities.
+pub fn html_to_text(html: &str) -> String {
+    let content_only = NON_CONTENT_BLOCK_RE.replace_all(html, " ");
+    let plain_text = HTML_TAG_RE.replace_all(&content_only, " ");
+    decode_html_entities(&plain_text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A run of prose under one heading; `heading` is empty for text before the first heading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProseSection {
+    pub heading: String,
+    pub text: String,
+}
+
+/// Splits rendered post HTML into plain-text sections at each heading, leaving
+/// out code blocks, math, the trailing hashtag line and URLs, so only the prose
+/// that describes what a post is about remains.
+pub fn prose_sections(content_html: &str) -> Vec<ProseSection> {
+    let html = CODE_BLOCK_RE.replace_all(content_html, " ");
+    let html = MATH_RE.replace_all(&html, " ");
+    let html = HASHTAG_PARAGRAPH_RE.replace_all(&html, " ");
+
+    let to_prose = |fragment: &str| {
+        let text = html_to_text(fragment);
+        BARE_URL_RE
+            .replace_all(&text, " ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut sections = Vec::new();
+    let mut heading = String::new();
+    let mut last = 0;
+    for caps in SECTION_HEADING_RE.captures_iter(&html) {
+        let full_match = caps.get(0).unwrap();
+        let text = to_prose(&html[last..full_match.start()]);
+        if !text.is_empty() {
+            sections.push(ProseSection { heading, text });
+        }
+        heading = to_prose(&caps[1]);
+        last = full_match.end();
+    }
+    let text = to_prose(&html[last..]);
+    if !text.is_empty() {
+        sections.push(ProseSection { heading, text });
+    }
+
+    sections
+}
+
 fn convert_hashtags_to_links(html: &str, tags: &[String]) -> String {
     if tags.is_empty() {
         return html.to_string();
@@ -791,11 +935,7 @@ fn convert_hashtags_to_links(html: &str, tags: &[String]) -> String {
         let hashtags_text = &caps[1];
         let links = HASHTAG_RE.replace_all(hashtags_text, |c: &regex::Captures| {
             let tag = &c[1];
-            format!(
-                r#"<a href="/index.html#{}">{}</a>"#,
-                tag,
-                format!("#{}", tag)
-            )
+            format!(r#"<a href="{}">#{}</a>"#, tag_url(tag), tag)
         });
         format!(r#"<p class="post-hashtags">{}</p>"#, links)
     })
@@ -857,3 +997,82 @@ pub fn format_date(date_str: &str) -> String {
         date_str.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_headings, markdown_to_html, prose_sections, ProseSection};
+
+    #[test]
+    fn extract_headings_preserves_explicit_ids() {
+        let headings =
+            extract_headings(r#"<h2 id="outlook">Outlook: why this trick stops at the plane</h2>"#);
+
+        assert_eq!(headings.len(), 1);
+        assert_eq!(headings[0].id, "outlook");
+    }
+
+    #[test]
+    fn extract_headings_generates_ids_when_missing() {
+        let headings = extract_headings("<h2>Why this trick works</h2>");
+
+        assert_eq!(headings.len(), 1);
+        assert_eq!(headings[0].id, "why-this-trick-works");
+    }
+
+    #[test]
+    fn prose_sections_split_at_headings_and_drop_code_math_and_urls() {
+        let html = concat!(
+            "<p>Intro with a link https://example.com/page.</p>",
+            r#"<div class="remark-highlight"><pre class="language-rust"><code>fn main() {}</code></pre></div>"#,
+            r#"<h2 id="energy">Energy</h2><p>Mass is <math><mi>E</mi></math> energy.</p>"#,
+            r##"<p class="post-hashtags"><a href="/#physics">#physics</a></p>"##,
+        );
+
+        assert_eq!(
+            prose_sections(html),
+            vec![
+                ProseSection {
+                    heading: String::new(),
+                    text: "Intro with a link".to_string(),
+                },
+                ProseSection {
+                    heading: "Energy".to_string(),
+                    text: "Mass is energy.".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn later_images_load_lazily_and_titles_become_captions() {
+        let html = markdown_to_html(
+            "![First](https://example.com/a.png)\n\n![Second](https://example.com/b.png \"A <b> plot\")\n",
+            &[],
+        );
+
+        assert_eq!(
+            html,
+            concat!(
+                "<p><img src=\"https://example.com/a.png\" alt=\"First\"></p>\n",
+                r#"<figure><img src="https://example.com/b.png" alt="Second" loading="lazy" decoding="async">"#,
+                "<figcaption>A &lt;b&gt; plot</figcaption></figure>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn captioned_images_among_text_stay_inside_the_paragraph() {
+        let html = markdown_to_html(
+            "See ![Plot](https://example.com/a.png \"Loss\") below.\n",
+            &[],
+        );
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<p>See <span class="figure"><img src="https://example.com/a.png" alt="Plot">"#,
+                "<span class=\"figcaption\">Loss</span></span> below.</p>\n",
+            )
+        );
+    }
+}
@@ -11,9 +11,14 @@ use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
-use tera::{Tera, Value};
+use tera::Tera;
 
+use crate::fingerprinting;
+use crate::optimization;
 use crate::page_generation::{extract_headings, format_date, Post, PostSummary};
+use crate::social_card_generation::{social_image_url, CARD_HEIGHT, CARD_WIDTH};
+use crate::structured_data::post_json_ld;
+use crate::urls::SiteUrls;
 
 const EXCERPT_MAX_CHARACTERS: usize = 160;
 
@@ -24,7 +29,7 @@ static NON_CONTENT_BLOCK_PATTERN: Lazy<Regex> = Lazy::new(|| {
 static HTML_TAG_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"<[^>]*>").expect("HTML tag regex must be valid"));
 
-fn extract_excerpt(content_html: &str) -> String {
+pub fn extract_excerpt(content_html: &str) -> String {
     let content_only = NON_CONTENT_BLOCK_PATTERN.replace_all(content_html, " ");
     let plain_text = HTML_TAG_PATTERN.replace_all(&content_only, " ");
 
@@ -37,28 +42,18 @@ fn extract_excerpt(content_html: &str) -> String {
         .collect()
 }
 
-/// Tera function that outputs a placeholder for CSS inlining.
-/// Usage in template: {{ inline_css(path="/styles/global.css") }}
-/// The placeholder is replaced with actual CSS content during optimization.
-fn inline_css_placeholder(args: &HashMap<String, Value>) -> tera::Result<Value> {
-    let path = args
-        .get("path")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| tera::Error::msg("inline_css requires a 'path' argument"))?;
-    Ok(Value::String(format!("<!-- INLINE_CSS:{} -->", path)))
-}
-
 pub fn build_post_pages(
     out_dir: &Path,
     posts: &Arc<Vec<Post>>,
-    similar_map: Option<&HashMap<String, Vec<PostSummary>>>,
+    similar_map: &HashMap<String, Vec<PostSummary>>,
+    urls: &SiteUrls,
 ) -> Result<()> {
     let start = Instant::now();
 
     let posts_out_dir = out_dir.join("posts");
     fs::create_dir_all(&posts_out_dir)?;
 
-    generate_all_post_pages(&posts_out_dir, posts, similar_map)?;
+    generate_all_post_pages(&posts_out_dir, posts, similar_map, urls)?;
 
     println!(
         "✓ Generated {} post pages in {:.2}s",
@@ -68,46 +63,16 @@ pub fn build_post_pages(
     Ok(())
 }
 
-pub fn get_related_posts(
-    all_posts: &[Post],
-    current_id: &str,
-    tags: &[String],
-    limit: usize,
-) -> Vec<PostSummary> {
-    if tags.is_empty() {
-        return Vec::new();
-    }
-
-    let first_tag = &tags[0];
-    let mut related: Vec<_> = all_posts
-        .iter()
-        .filter(|p| p.id != current_id && p.tags.contains(first_tag))
-        .take(10)
-        .map(|p| PostSummary {
-            id: p.id.clone(),
-            title: p.title.clone(),
-            date: p.date.clone(),
-            tags: p.tags.clone(),
-            icon: p.icon.clone(),
-        })
-        .collect();
-
-    if related.len() <= limit {
-        return related;
-    }
-
-    // Take first 2 and one from middle
-    let mut result = related.drain(0..2).collect::<Vec<_>>();
-    if !related.is_empty() {
-        let idx = (related.len() as f64 * 0.5) as usize;
-        result.push(related[idx].clone());
-    }
-    result
-}
-
-pub fn generate_post_page(out_dir: &Path, post: &Post, related: &[PostSummary]) -> Result<()> {
+pub fn generate_post_page(
+    out_dir: &Path,
+    post: &Post,
+    related: &[PostSummary],
+    urls: &SiteUrls,
+) -> Result<()> {
     let mut tera = Tera::new("website/post/**/*")?;
-    tera.register_function("inline_css", inline_css_placeholder);
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
 
     // Detect if page has code blocks
     let has_code_blocks = post.content_html.contains("<pre");
@@ -140,6 +105,7 @@ pub fn generate_post_page(out_dir: &Path, post: &Post, related: &[PostSummary])
                 "title": rel.title,
                 "formatted_date": format_date(&rel.date),
                 "icon": rel.icon,
+                "score": rel.score,
             })
         })
         .collect();
@@ -148,6 +114,7 @@ pub fn generate_post_page(out_dir: &Path, post: &Post, related: &[PostSummary])
     context.insert("post_title", &post.title);
     context.insert("post_icon", &post.icon);
     context.insert("post_id", &post.id);
+    context.insert("noindex", &post.noindex);
     context.insert("excerpt", &excerpt);
     context.insert("keywords", &keywords);
     context.insert("has_toc", &has_toc);
@@ -155,14 +122,15 @@ pub fn generate_post_page(out_dir: &Path, post: &Post, related: &[PostSummary])
     context.insert("has_code_blocks", &has_code_blocks);
     context.insert("title_id", &title_id);
     context.insert("post_date", &post.date);
-    context.insert("post_date_modified", &post.date_modified);
-    context.insert(
-        "post_tags_first",
-        &post.tags.first().map(|s| s.as_str()).unwrap_or(""),
-    );
     context.insert("formatted_date", &format_date(&post.date));
     context.insert("content_html", &post.content_html);
     context.insert("related_posts", &related_data);
+    context.insert("json_ld", &post_json_ld(post, urls)?);
+    context.insert("social_image", &social_image_url(post, urls));
+    // Generated cards have a known size; cover images may have any
+    context.insert("social_image_is_card", &post.image.is_none());
+    context.insert("card_width", &CARD_WIDTH);
+    context.insert("card_height", &CARD_HEIGHT);
 
     let html = tera.render("post.html", &context)?;
 
@@ -179,18 +147,19 @@ pub fn generate_post_page(out_dir: &Path, post: &Post, related: &[PostSummary])
 pub fn generate_all_post_pages(
     posts_out_dir: &Path,
     posts: &Arc<Vec<Post>>,
-    similar_map: Option<&HashMap<String, Vec<PostSummary>>>,
+    similar_map: &HashMap<String, Vec<PostSummary>>,
+    urls: &SiteUrls,
 ) -> Result<()> {
     let post_ids: Vec<String> = posts.iter().map(|p| p.id.clone()).collect();
     let completed = std::sync::atomic::AtomicUsize::new(0);
 
     post_ids.par_iter().try_for_each(|post_id| -> Result<()> {
         let post = posts.iter().find(|p| &p.id == post_id).unwrap();
-        let related = match similar_map.and_then(|m| m.get(post_id)) {
-            Some(similar) => similar.clone(),
-            None => get_related_posts(posts, post_id, &post.tags, 3),
-        };
-        generate_post_page(posts_out_dir, post, &related)?;
+        let related = similar_map
+            .get(post_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        generate_post_page(posts_out_dir, post, related, urls)?;
         completed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         Ok(())
     })?;
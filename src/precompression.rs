@@ -0,0 +1,186 @@
+//! Precompressed `.gz` and `.br` siblings of the site's text assets.
+//!
+//! Static servers set up for it (nginx `gzip_static`/`brotli_static`,
+//! Caddy `precompressed`) send `index.html.br` in place of `index.html` to
+//! clients that accept it, without compressing on every request. Both
+//! encodings run at their highest quality, since it is paid once per build.
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rayon::prelude::*;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use walkdir::WalkDir;
+
+/// Extensions of the files that get compressed siblings, in report order.
+const COMPRESSED_EXTENSIONS: &[&str] = &["html", "css", "js", "xml", "json", "svg"];
+/// Files smaller than this, in bytes, gain too little to be worth a sibling.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+const BROTLI_QUALITY: i32 = 11;
+/// Base-2 logarithm of the brotli window size.
+const BROTLI_WINDOW: i32 = 22;
+
+/// Sizes of one file as sent to clients.
+#[derive(Debug, Default, PartialEq)]
+struct TransferSizes {
+    original: usize,
+    /// Size of the `.gz` sibling, or the original if it has none.
+    gzip: usize,
+    /// Size of the `.br` sibling, or the original if it has none.
+    brotli: usize,
+    /// Siblings written.
+    siblings: usize,
+}
+
+/// Writes the `.gz` and `.br` siblings of every text asset in `out_dir`
+/// and reports the transfer sizes per asset type.
+pub fn precompress_assets(out_dir: &Path) -> Result<()> {
+    let start = Instant::now();
+
+    let files: Vec<(PathBuf, &str)> = WalkDir::new(out_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let extension = entry.path().extension()?.to_str()?.to_ascii_lowercase();
+            let extension = COMPRESSED_EXTENSIONS
+                .iter()
+                .find(|compressed| **compressed == extension)?;
+            Some((entry.into_path(), *extension))
+        })
+        .collect();
+    let sizes: Vec<TransferSizes> = files
+        .par_iter()
+        .map(|(path, _)| precompress_file(path))
+        .collect::<Result<_>>()?;
+
+    println!(
+        "✓ Precompressed {} files with gzip and brotli in {:.2}s",
+        sizes.iter().filter(|sizes| sizes.siblings > 0).count(),
+        start.elapsed().as_secs_f64()
+    );
+    for extension in COMPRESSED_EXTENSIONS {
+        let mut total = TransferSizes::default();
+        let mut count = 0;
+        for ((_, file_extension), sizes) in files.iter().zip(&sizes) {
+            if file_extension == extension {
+                count += 1;
+                total.original += sizes.original;
+                total.gzip += sizes.gzip;
+                total.brotli += sizes.brotli;
+            }
+        }
+        if count == 0 {
+            continue;
+        }
+        println!(
+            "  ({}: {} files, {:.2} KB → {:.2} KB gzip, {:.2} KB brotli)",
+            extension,
+            count,
+            total.original as f64 / 1024.0,
+            total.gzip as f64 / 1024.0,
+            total.brotli as f64 / 1024.0
+        );
+    }
+    Ok(())
+}
+
+/// Writes the siblings of the file at `path` that are smaller than it.
+fn precompress_file(path: &Path) -> Result<TransferSizes> {
+    let data = fs::read(path)?;
+    let mut sizes = TransferSizes {
+        original: data.len(),
+        gzip: data.len(),
+        brotli: data.len(),
+        siblings: 0,
+    };
+    if data.len() < MIN_COMPRESSED_SIZE {
+        return Ok(sizes);
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    for (suffix, compressed, size) in [
+        ("gz", gzip(&data)?, &mut sizes.gzip),
+        ("br", brotli(&data)?, &mut sizes.brotli),
+    ] {
+        if compressed.len() < data.len() {
+            fs::write(
+                path.with_file_name(format!("{}.{}", name, suffix)),
+                &compressed,
+            )?;
+            *size = compressed.len();
+            sizes.siblings += 1;
+        }
+    }
+    Ok(sizes)
+}
+
+/// Gzip without a modification time or file name, so builds stay reproducible.
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn brotli(data: &[u8]) -> io::Result<Vec<u8>> {
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: BROTLI_QUALITY,
+        lgwin: BROTLI_WINDOW,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{precompress_file, TransferSizes};
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn siblings_decompress_to_the_original_and_small_files_get_none() {
+        let dir =
+            std::env::temp_dir().join(format!("blog-builder-precompress-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let html = "<p>Zipf's law</p>\n".repeat(200);
+        fs::write(dir.join("index.html"), &html).unwrap();
+        fs::write(dir.join("small.css"), "p{margin:0}").unwrap();
+
+        let sizes = precompress_file(&dir.join("index.html")).unwrap();
+        let small = precompress_file(&dir.join("small.css")).unwrap();
+        let gzip = fs::read(dir.join("index.html.gz")).unwrap();
+        let brotli = fs::read(dir.join("index.html.br")).unwrap();
+        let small_has_siblings = dir.join("small.css.gz").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(sizes.siblings, 2);
+        assert_eq!((sizes.gzip, sizes.brotli), (gzip.len(), brotli.len()));
+        let mut unzipped = String::new();
+        flate2::read::GzDecoder::new(&gzip[..])
+            .read_to_string(&mut unzipped)
+            .unwrap();
+        assert_eq!(unzipped, html);
+        let mut unbrotlied = String::new();
+        brotli::Decompressor::new(&brotli[..], 4096)
+            .read_to_string(&mut unbrotlied)
+            .unwrap();
+        assert_eq!(unbrotlied, html);
+
+        assert!(!small_has_siblings);
+        assert_eq!(
+            small,
+            TransferSizes {
+                original: 11,
+                gzip: 11,
+                brotli: 11,
+                siblings: 0,
+            }
+        );
+    }
+}
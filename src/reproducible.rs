@@ -0,0 +1,175 @@
+//! Reproducible builds: the same sources must always produce the same bytes,
+//! so the site's IPFS CID only changes when its content does.
+//!
+//! The only wall-clock input left is the build time (the copyright year),
+//! which honours `SOURCE_DATE_EPOCH` (https://reproducible-builds.org/specs/source-date-epoch/).
+//! `--verify-reproducible` checks the rest by building twice and diffing.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Differences listed before the report is cut short.
+const MAX_REPORTED_DIFFERENCES: usize = 20;
+
+static BUILD_TIME: Lazy<DateTime<Utc>> = Lazy::new(|| {
+    let Ok(value) = std::env::var("SOURCE_DATE_EPOCH") else {
+        return Utc::now();
+    };
+    parse_source_date_epoch(&value).unwrap_or_else(|| {
+        eprintln!(
+            "Warning: Ignoring invalid SOURCE_DATE_EPOCH '{}', using the current time",
+            value
+        );
+        Utc::now()
+    })
+});
+
+/// Time the site is built at: `SOURCE_DATE_EPOCH` if set, otherwise now.
+/// Fixed for the whole process, so every page of a build agrees.
+pub fn build_time() -> DateTime<Utc> {
+    *BUILD_TIME
+}
+
+fn parse_source_date_epoch(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(value.trim().parse().ok()?, 0)
+}
+
+/// Builds the site twice into fresh temporary directories and compares the
+/// results byte for byte, failing if any file differs.
+///
+/// Each build runs in its own process with the same command line and starts
+/// from its own empty cache directory, so per-process state such as randomly
+/// seeded hash maps cannot hide behind cached results. Both builds are pinned
+/// to one `SOURCE_DATE_EPOCH`.
+pub fn verify_reproducible() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("blog-builder-verify-{}", std::process::id()));
+    let outputs = [root.join("first"), root.join("second")];
+
+    let result = outputs
+        .iter()
+        .try_for_each(|out_dir| build_in_child_process(out_dir, &out_dir.with_extension("cache")))
+        .and_then(|()| diff_outputs(&outputs[0], &outputs[1]));
+    fs::remove_dir_all(&root).ok();
+    let (files, differences) = result?;
+
+    if differences.is_empty() {
+        println!("\n✓ Build is reproducible: {} files identical", files);
+        return Ok(());
+    }
+
+    eprintln!();
+    for difference in differences.iter().take(MAX_REPORTED_DIFFERENCES) {
+        eprintln!("  {}", difference);
+    }
+    if differences.len() > MAX_REPORTED_DIFFERENCES {
+        eprintln!(
+            "  ... and {} more",
+            differences.len() - MAX_REPORTED_DIFFERENCES
+        );
+    }
+    bail!(
+        "Build is not reproducible: {} of {} files differ between two builds",
+        differences.len(),
+        files
+    )
+}
+
+/// Re-runs this executable with the current arguments, minus
+/// `--verify-reproducible`, writing the site to `out_dir` and its caches to
+/// `cache_dir`.
+fn build_in_child_process(out_dir: &Path, cache_dir: &Path) -> Result<()> {
+    let args = std::env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--verify-reproducible");
+    let status = Command::new(std::env::current_exe()?)
+        .args(args)
+        .arg("--out-dir")
+        .arg(out_dir)
+        .arg("--cache-dir")
+        .arg(cache_dir)
+        .env("SOURCE_DATE_EPOCH", build_time().timestamp().to_string())
+        .status()
+        .context("Failed to start a verification build")?;
+    if !status.success() {
+        bail!("Verification build into {} failed", out_dir.display());
+    }
+    Ok(())
+}
+
+/// Compares two output trees, returning the number of files seen and a
+/// description of every file that is missing from one side or differs.
+fn diff_outputs(first: &Path, second: &Path) -> Result<(usize, Vec<String>)> {
+    let first_files = relative_files(first)?;
+    let second_files = relative_files(second)?;
+
+    let mut differences = Vec::new();
+    let all_files: BTreeSet<&PathBuf> = first_files.union(&second_files).collect();
+    for path in &all_files {
+        let difference = match (first_files.contains(*path), second_files.contains(*path)) {
+            (true, false) => Some("only in first build"),
+            (false, true) => Some("only in second build"),
+            _ => (fs::read(first.join(path))? != fs::read(second.join(path))?)
+                .then_some("content differs"),
+        };
+        if let Some(difference) = difference {
+            differences.push(format!("{} ({})", path.display(), difference));
+        }
+    }
+
+    Ok((all_files.len(), differences))
+}
+
+fn relative_files(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.insert(entry.path().strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_outputs, parse_source_date_epoch};
+    use std::fs;
+
+    #[test]
+    fn source_date_epoch_is_seconds_since_1970() {
+        let time = parse_source_date_epoch("1700000000\n").unwrap();
+        assert_eq!(time.to_rfc3339(), "2023-11-14T22:13:20+00:00");
+        assert!(parse_source_date_epoch("yesterday").is_none());
+    }
+
+    #[test]
+    fn diff_reports_changed_and_missing_files() {
+        let root = std::env::temp_dir().join(format!("blog-builder-diff-{}", std::process::id()));
+        let (first, second) = (root.join("a"), root.join("b"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir.join("posts")).unwrap();
+            fs::write(dir.join("index.html"), "same").unwrap();
+        }
+        fs::write(first.join("posts/x.html"), "1").unwrap();
+        fs::write(second.join("posts/x.html"), "2").unwrap();
+        fs::write(second.join("extra.txt"), "").unwrap();
+
+        let (files, differences) = diff_outputs(&first, &second).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(files, 3);
+        assert_eq!(
+            differences,
+            [
+                "extra.txt (only in second build)",
+                "posts/x.html (content differs)"
+            ]
+        );
+    }
+}
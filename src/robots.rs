@@ -0,0 +1,254 @@
+//! Crawler policy: `robots.txt` and `noindex` pages.
+//!
+//! `robots.txt` is rendered from the `robots` section of `site.yaml`.
+//! Pages matching its `noindex` patterns get a `<meta name="robots">` tag
+//! after generation; together with posts whose frontmatter sets
+//! `noindex: true`, they are left out of the sitemap and `llms.txt`.
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::config::RobotsConfig;
+use crate::page_generation::Post;
+use crate::urls::{SiteUrls, BASE_URL};
+
+/// User agents of crawlers that collect training data for AI models, as
+/// published by their operators.
+pub const AI_TRAINING_CRAWLERS: &[&str] = &[
+    "GPTBot",
+    "ClaudeBot",
+    "anthropic-ai",
+    "Google-Extended",
+    "Applebot-Extended",
+    "CCBot",
+    "Bytespider",
+    "meta-externalagent",
+    "FacebookBot",
+    "cohere-training-data-crawler",
+    "AI2Bot",
+    "Diffbot",
+    "PanguBot",
+    "Timpibot",
+    "ImagesiftBot",
+    "omgili",
+];
+
+static ROBOTS_META_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<meta\b[^>]*\bname\s*=\s*"robots"[^>]*\bcontent\s*=\s*"([^"]*)"[^>]*>"#)
+        .unwrap()
+});
+
+pub fn render_robots_txt(config: &RobotsConfig) -> Result<String> {
+    let mut out = String::new();
+    for rule in &config.rules {
+        if rule.user_agents.is_empty() {
+            bail!("robots rule without user agents in site config");
+        }
+        if let Some(path) = rule
+            .allow
+            .iter()
+            .chain(&rule.disallow)
+            .find(|path| !path.starts_with(['/', '*']))
+        {
+            bail!("robots path '{}' must start with '/' or '*'", path);
+        }
+
+        for agent in &rule.user_agents {
+            let _ = writeln!(out, "User-agent: {}", agent);
+        }
+        for path in &rule.allow {
+            let _ = writeln!(out, "Allow: {}", path);
+        }
+        for path in &rule.disallow {
+            let _ = writeln!(out, "Disallow: {}", path);
+        }
+        if rule.allow.is_empty() && rule.disallow.is_empty() {
+            // A group needs at least one rule; an empty Disallow allows everything
+            out.push_str("Disallow:\n");
+        }
+        out.push('\n');
+    }
+
+    if config.block_ai_training {
+        // Crawlers follow only the most specific group, so ones named by a
+        // rule keep that rule
+        let named = |crawler: &str| {
+            config
+                .rules
+                .iter()
+                .flat_map(|rule| &rule.user_agents)
+                .any(|agent| agent.eq_ignore_ascii_case(crawler))
+        };
+        let crawlers: Vec<&str> = AI_TRAINING_CRAWLERS
+            .iter()
+            .copied()
+            .filter(|crawler| !named(crawler))
+            .collect();
+        if !crawlers.is_empty() {
+            for crawler in crawlers {
+                let _ = writeln!(out, "User-agent: {}", crawler);
+            }
+            out.push_str("Disallow: /\n\n");
+        }
+    }
+
+    let _ = writeln!(out, "Sitemap: {}/sitemap.xml", BASE_URL);
+    Ok(out)
+}
+
+/// Whether the page at canonical `path` matches a `noindex` pattern.
+pub fn is_noindex_path(config: &RobotsConfig, path: &str, urls: &SiteUrls) -> bool {
+    config
+        .noindex
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => urls.canonical_path(pattern) == path,
+        })
+}
+
+/// Whether `html` asks search engines not to index it.
+pub fn is_noindex_page(html: &str) -> bool {
+    ROBOTS_META_RE
+        .captures_iter(html)
+        .any(|caps| has_noindex(&caps[1]))
+}
+
+fn has_noindex(content: &str) -> bool {
+    content.split(',').any(|directive| {
+        matches!(
+            directive.trim().to_ascii_lowercase().as_str(),
+            "noindex" | "none"
+        )
+    })
+}
+
+/// Marks the emitted pages matching a `noindex` pattern.
+pub fn apply_noindex(out_dir: &Path, config: &RobotsConfig, urls: &SiteUrls) -> Result<()> {
+    if config.noindex.is_empty() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(out_dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some("html")
+        {
+            continue;
+        }
+        if !is_noindex_path(config, &urls.for_file(path.strip_prefix(out_dir)?), urls) {
+            continue;
+        }
+        if let Some(html) = add_noindex(&fs::read_to_string(path)?) {
+            fs::write(path, html)?;
+        }
+    }
+    Ok(())
+}
+
+/// `html` with `noindex` added to its robots meta tag, or a new one, or
+/// `None` if it already has it.
+fn add_noindex(html: &str) -> Option<String> {
+    if is_noindex_page(html) {
+        return None;
+    }
+    if let Some(content) = ROBOTS_META_RE.captures(html).and_then(|caps| caps.get(1)) {
+        let directives = if content.as_str().trim().is_empty() {
+            "noindex".to_string()
+        } else {
+            format!("noindex, {}", content.as_str().trim())
+        };
+        return Some(format!(
+            "{}{}{}",
+            &html[..content.start()],
+            directives,
+            &html[content.end()..]
+        ));
+    }
+    let head_end = html.to_ascii_lowercase().find("</head>")?;
+    Some(format!(
+        "{}  <meta name=\"robots\" content=\"noindex\">\n{}",
+        &html[..head_end],
+        &html[head_end..]
+    ))
+}
+
+/// The posts search engines may index, in their original order.
+pub fn indexed_posts(posts: &[Post], config: &RobotsConfig, urls: &SiteUrls) -> Vec<Post> {
+    posts
+        .iter()
+        .filter(|post| !post.noindex && !is_noindex_path(config, &urls.post(&post.id), urls))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_noindex, is_noindex_path, render_robots_txt};
+    use crate::config::{RobotsConfig, RobotsRule};
+    use crate::urls::{SiteUrls, TrailingSlash};
+
+    #[test]
+    fn ai_preset_skips_crawlers_with_their_own_rule() {
+        let config = RobotsConfig {
+            rules: vec![
+                RobotsRule {
+                    user_agents: vec!["*".to_string()],
+                    allow: Vec::new(),
+                    disallow: vec!["/drafts/".to_string()],
+                },
+                RobotsRule {
+                    user_agents: vec!["gptbot".to_string()],
+                    allow: vec!["/".to_string()],
+                    disallow: Vec::new(),
+                },
+            ],
+            block_ai_training: true,
+            noindex: Vec::new(),
+        };
+
+        let robots = render_robots_txt(&config).unwrap();
+
+        assert!(robots.starts_with(
+            "User-agent: *\nDisallow: /drafts/\n\nUser-agent: gptbot\nAllow: /\n\nUser-agent: ClaudeBot\n"
+        ));
+        assert!(!robots.contains("User-agent: GPTBot"));
+        assert!(robots.ends_with(
+            "User-agent: omgili\nDisallow: /\n\nSitemap: https://seanpedersen.github.io/sitemap.xml\n"
+        ));
+    }
+
+    #[test]
+    fn noindex_patterns_match_canonical_paths_and_prefixes() {
+        let urls = SiteUrls::new(TrailingSlash::Never);
+        let config = RobotsConfig {
+            noindex: vec!["/search/".to_string(), "/posts/draft-*".to_string()],
+            ..RobotsConfig::default()
+        };
+
+        assert!(is_noindex_path(&config, "/search", &urls));
+        assert!(is_noindex_path(&config, "/posts/draft-zipf", &urls));
+        assert!(!is_noindex_path(&config, "/posts/zipf", &urls));
+    }
+
+    #[test]
+    fn noindex_joins_an_existing_robots_meta() {
+        assert_eq!(
+            add_noindex(r#"<head><meta name="robots" content="nofollow"></head>"#).unwrap(),
+            r#"<head><meta name="robots" content="noindex, nofollow"></head>"#
+        );
+        assert_eq!(
+            add_noindex("<head>\n</head>").unwrap(),
+            "<head>\n  <meta name=\"robots\" content=\"noindex\">\n</head>"
+        );
+        assert_eq!(
+            add_noindex(r#"<meta name="robots" content="noindex, follow">"#),
+            None
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Builds the offline full-text search index and the `/search/` page.
+//!
+//! Every post is tokenized, stemmed and weighted by field (title, tags,
+//! headings, body). The resulting inverted index is sharded by the first
+//! character of each term so the browser only downloads the shards a query
+//! actually touches. Plain text per post is written separately and fetched
+//! lazily to build result snippets.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use tera::Tera;
+
+use crate::fingerprinting;
+use crate::optimization;
+use crate::page_generation::{extract_headings, html_to_text, Post};
+use crate::urls::SiteUrls;
+
+const TITLE_WEIGHT: u32 = 10;
+const TAG_WEIGHT: u32 = 6;
+const HEADING_WEIGHT: u32 = 4;
+const BODY_WEIGHT: u32 = 1;
+
+/// Tokens shorter than this carry almost no signal and bloat the index.
+const MIN_TOKEN_CHARS: usize = 2;
+/// Longer tokens are almost always hashes, URLs or base64 noise.
+const MAX_TOKEN_CHARS: usize = 32;
+
+/// Shard key for terms that do not start with an ASCII letter or digit.
+const FALLBACK_SHARD: char = '_';
+
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "because",
+    "been", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have",
+    "he", "her", "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "more", "most",
+    "my", "no", "not", "of", "on", "one", "only", "or", "other", "our", "out", "over", "she", "so",
+    "some", "such", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "those", "to", "too", "up", "us", "very", "was", "we", "were", "what", "when", "which",
+    "who", "will", "with", "would", "you", "your",
+];
+
+static STOPWORD_SET: Lazy<HashSet<&'static str>> =
+    Lazy::new(|| STOPWORDS.iter().copied().collect());
+static STEMMER: Lazy<Stemmer> = Lazy::new(|| Stemmer::create(Algorithm::English));
+
+#[derive(Serialize)]
+struct SearchDocument<'a> {
+    id: &'a str,
+    url: String,
+    title: &'a str,
+    date: &'a str,
+    tags: &'a [String],
+}
+
+/// Entry point of the index, fetched once before any shard.
+#[derive(Serialize)]
+struct SearchMeta<'a> {
+    docs: Vec<SearchDocument<'a>>,
+    shards: Vec<String>,
+    stopwords: &'static [&'static str],
+    min_token_chars: usize,
+}
+
+/// term -> [(document index, weighted term frequency)]
+type InvertedIndex = BTreeMap<String, Vec<(u32, u32)>>;
+/// The slice of an [`InvertedIndex`] stored in one `index-<key>.json` file.
+type IndexShard<'a> = BTreeMap<&'a str, &'a [(u32, u32)]>;
+
+pub fn build_search_index(out_dir: &Path, posts: &Arc<Vec<Post>>, urls: &SiteUrls) -> Result<()> {
+    let start = Instant::now();
+
+    let search_dir = out_dir.join("search");
+    let text_dir = search_dir.join("text");
+    fs::create_dir_all(&text_dir)?;
+
+    let mut index = InvertedIndex::new();
+    for (doc, post) in posts.iter().enumerate() {
+        let body_text = html_to_text(&post.content_html);
+        for (term, weight) in weighted_terms(post, &body_text) {
+            index.entry(term).or_default().push((doc as u32, weight));
+        }
+        fs::write(text_dir.join(format!("{}.txt", post.id)), &body_text)?;
+    }
+
+    let shards = write_shards(&search_dir, &index)?;
+
+    let meta = SearchMeta {
+        docs: posts
+            .iter()
+            .map(|p| SearchDocument {
+                id: &p.id,
+                url: urls.post(&p.id),
+                title: &p.title,
+                date: &p.date,
+                tags: &p.tags,
+            })
+            .collect(),
+        shards,
+        stopwords: STOPWORDS,
+        min_token_chars: MIN_TOKEN_CHARS,
+    };
+    fs::write(search_dir.join("meta.json"), serde_json::to_string(&meta)?)?;
+
+    generate_search_page(&search_dir, urls)?;
+
+    println!(
+        "✓ Generated search index ({} terms) in {:.2}s",
+        index.len(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn weighted_terms(post: &Post, body_text: &str) -> BTreeMap<String, u32> {
+    let mut weights = BTreeMap::new();
+    let mut add = |text: &str, weight: u32| {
+        for term in tokenize(text) {
+            *weights.entry(term).or_insert(0) += weight;
+        }
+    };
+
+    add(&post.title, TITLE_WEIGHT);
+    for tag in &post.tags {
+        add(tag, TAG_WEIGHT);
+    }
+    for heading in extract_headings(&post.content_html) {
+        add(&heading.text, HEADING_WEIGHT);
+    }
+    add(body_text, BODY_WEIGHT);
+
+    weights
+}
+
+/// Lowercases, splits on anything that is not a letter or digit, drops
+/// stopwords and stems what is left. The search page mirrors the split and
+/// stopword rules (shipped in `meta.json`) but not the stemming, so it
+/// matches query words against stems by prefix instead.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| {
+            let len = word.chars().count();
+            (MIN_TOKEN_CHARS..=MAX_TOKEN_CHARS).contains(&len)
+        })
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOPWORD_SET.contains(word.as_str()))
+        .map(|word| STEMMER.stem(&word).into_owned())
+}
+
+fn shard_key(term: &str) -> char {
+    match term.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => c,
+        _ => FALLBACK_SHARD,
+    }
+}
+
+/// Writes one `index-<key>.json` per shard and returns the shard keys.
+fn write_shards(search_dir: &Path, index: &InvertedIndex) -> Result<Vec<String>> {
+    let mut shards: BTreeMap<char, IndexShard> = BTreeMap::new();
+    for (term, postings) in index {
+        shards
+            .entry(shard_key(term))
+            .or_default()
+            .insert(term, postings);
+    }
+
+    for (key, terms) in &shards {
+        let path = search_dir.join(format!("index-{}.json", key));
+        fs::write(path, serde_json::to_string(terms)?)?;
+    }
+
+    Ok(shards.keys().map(|key| key.to_string()).collect())
+}
+
+fn generate_search_page(search_dir: &Path, urls: &SiteUrls) -> Result<()> {
+    let mut tera = Tera::new("website/search/**/*.html")?;
+    tera.register_function("inline_css", optimization::inline_css_placeholder);
+    tera.register_function("url_for", urls.tera_function());
+    tera.register_function("asset_url", fingerprinting::asset_url);
+
+    let html = tera.render("search.html", &tera::Context::new())?;
+
+    let mut file = BufWriter::new(File::create(search_dir.join("index.html"))?);
+    write!(file, "{}", html)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shard_key, tokenize};
+
+    #[test]
+    fn tokenize_drops_stopwords_and_stems() {
+        let terms: Vec<String> = tokenize("The Networks are learning").collect();
+
+        assert_eq!(terms, vec!["network", "learn"]);
+    }
+
+    #[test]
+    fn tokenize_skips_single_characters_and_noise() {
+        let terms: Vec<String> = tokenize("x = 0f3c9a1b2d4e5f60718293a4b5c6d7e8f9a0b1c2").collect();
+
+        assert!(terms.is_empty());
+    }
+
+    #[test]
+    fn shard_key_groups_non_ascii_terms() {
+        assert_eq!(shard_key("entropy"), 'e');
+        assert_eq!(shard_key("42"), '4');
+        assert_eq!(shard_key("über"), '_');
+    }
+}
@@ -1,80 +1,477 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use crate::page_generation::{Post, PostSummary};
 
+/// Settings for `--smart-similar`, collected from the command line.
+#[cfg_attr(not(feature = "smart-similar"), allow(dead_code))]
+pub struct SmartSimilarOptions {
+    pub model_path: Option<PathBuf>,
+    /// Directory the embedding cache is kept in.
+    pub cache_dir: PathBuf,
+    pub pooling: ChunkPooling,
+    /// Weight of the title embedding relative to the pooled body (1.0).
+    pub title_weight: f32,
+    /// Weight of the tag embedding relative to the pooled body (1.0).
+    pub tag_weight: f32,
+}
+
+/// Output of `--smart-similar`: related posts and the embeddings they were computed from.
+#[cfg_attr(not(feature = "smart-similar"), allow(dead_code))]
+pub struct SmartSimilarity {
+    pub similar_map: HashMap<String, Vec<PostSummary>>,
+    /// One unit-length vector per post, in the same order as the posts.
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// How chunk embeddings of one post are combined into the body embedding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChunkPooling {
+    /// Average all chunks, so longer sections weigh more.
+    Mean,
+    /// Average chunks per section first, so every section weighs the same.
+    Section,
+}
+
+/// Number of related posts listed under each post unless configured otherwise.
+pub const DEFAULT_RELATED_COUNT: usize = 3;
+/// Trade-off between relevance (1.0) and variety among picks (0.0) in MMR re-ranking.
+pub const DEFAULT_RELATED_DIVERSITY: f32 = 0.7;
+/// Embedding cosine similarity below which two posts are not considered related.
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.5;
+
+/// How related posts are picked from the scored candidates, shared by both engines.
+pub struct RelatedSelection {
+    pub count: usize,
+    /// Candidates scoring below this are never shown.
+    pub min_score: f32,
+    /// MMR lambda: 1.0 ranks purely by relevance, lower values penalize
+    /// candidates that resemble posts already picked.
+    pub diversity: f32,
+}
+
+/// Picks related posts for `posts[current]`. Posts listed under `related:` in
+/// its frontmatter come first; the remaining slots are filled from `scored`
+/// candidates above `selection.min_score` by Maximal Marginal Relevance, where
+/// `pair_score(a, b)` measures how alike two candidates are.
+pub fn select_related(
+    posts: &[Post],
+    current: usize,
+    scored: &[(usize, f32)],
+    pair_score: impl Fn(usize, usize) -> f32,
+    selection: &RelatedSelection,
+) -> Vec<PostSummary> {
+    let score_of = |j: usize| scored.iter().find(|&&(k, _)| k == j).map(|&(_, s)| s);
+
+    let mut picked: Vec<usize> = Vec::new();
+    for id in &posts[current].related {
+        match posts.iter().position(|p| &p.id == id) {
+            Some(j) if j != current && !picked.contains(&j) => picked.push(j),
+            Some(_) => {}
+            None => eprintln!(
+                "Warning: Post '{}' lists unknown related post '{}'",
+                posts[current].id, id
+            ),
+        }
+    }
+    let pinned = picked.len();
+
+    let mut candidates: Vec<(usize, f32)> = scored
+        .iter()
+        .copied()
+        .filter(|&(j, score)| j != current && score >= selection.min_score && !picked.contains(&j))
+        .collect();
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    while picked.len() < selection.count.max(pinned) && !candidates.is_empty() {
+        let (best, _) = candidates
+            .iter()
+            .enumerate()
+            .map(|(c, &(j, relevance))| {
+                let redundancy = picked
+                    .iter()
+                    .map(|&p| pair_score(j, p))
+                    .fold(0.0_f32, f32::max);
+                let mmr =
+                    selection.diversity * relevance - (1.0 - selection.diversity) * redundancy;
+                (c, mmr)
+            })
+            .fold((0, f32::NEG_INFINITY), |best, current| {
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            });
+        picked.push(candidates.remove(best).0);
+    }
+
+    picked
+        .into_iter()
+        .map(|j| {
+            let mut summary = post_to_summary(&posts[j]);
+            summary.score = score_of(j);
+            summary
+        })
+        .collect()
+}
+
+fn post_to_summary(post: &Post) -> PostSummary {
+    PostSummary {
+        id: post.id.clone(),
+        title: post.title.clone(),
+        date: post.date.clone(),
+        tags: post.tags.clone(),
+        icon: post.icon.clone(),
+        score: None,
+    }
+}
+
 #[cfg(feature = "smart-similar")]
 mod inner {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
     use std::time::Instant;
 
-    use anyhow::Result;
+    use anyhow::{Context, Result};
     use model2vec_rs::model::StaticModel;
+    use rayon::prelude::*;
 
-    use crate::page_generation::{Post, PostSummary};
+    use super::{
+        select_related, ChunkPooling, RelatedSelection, SmartSimilarOptions, SmartSimilarity,
+    };
+    use crate::ann_index::nearest_neighbours;
+    use crate::embedding_cache::{cache_key, EmbeddingCache, EMBEDDING_CACHE_FILE};
+    use crate::page_generation::{prose_sections, Post, ProseSection};
 
     const MODEL_NAME: &str = "minishlab/potion-multilingual-128M";
-    const EMBED_TEXT_CHAR_LIMIT: usize = 1024;
-    const TOP_SIMILAR_COUNT: usize = 3;
+    /// Files `StaticModel::from_pretrained` reads from a model directory.
+    const MODEL_FILES: &[&str] = &["tokenizer.json", "model.safetensors", "config.json"];
+    /// Words per embedded chunk; static models average token vectors, so
+    /// much longer chunks blur into a generic "average text" direction.
+    const CHUNK_WORDS: usize = 200;
+    /// Words shared by consecutive chunks so no sentence is only ever seen cut in half.
+    const CHUNK_OVERLAP_WORDS: usize = 50;
+    /// Nearest neighbours fetched per related post slot, giving MMR room to diversify.
+    const CANDIDATES_PER_RELATED_POST: usize = 5;
 
-    pub fn compute_similar_posts(posts: &[Post]) -> Result<HashMap<String, Vec<PostSummary>>> {
+    /// Where the embedding model comes from and the id its embeddings are cached under.
+    struct ModelSource {
+        id: String,
+        /// A local directory, or the Hugging Face repo name when it still has to be downloaded.
+        location: String,
+        needs_download: bool,
+    }
+
+    /// Everything embedded for one post: body chunks grouped by section, title and tags.
+    struct PostTexts {
+        sections: Vec<Vec<String>>,
+        title: String,
+        tags: Option<String>,
+    }
+
+    pub fn compute_similar_posts(
+        posts: &[Post],
+        options: &SmartSimilarOptions,
+        selection: &RelatedSelection,
+    ) -> Result<SmartSimilarity> {
         let start = Instant::now();
-        println!("Loading embedding model ({})...", MODEL_NAME);
-        let model = StaticModel::from_pretrained(MODEL_NAME, None, None, None)?;
-        println!("  Model loaded in {:.2}s", start.elapsed().as_secs_f64());
+        let embeddings = post_embeddings(posts, options)?;
 
-        let texts: Vec<String> = posts
-            .iter()
-            .map(|p| build_embed_text(&p.title, &p.content_raw))
+        let neighbours =
+            nearest_neighbours(&embeddings, selection.count * CANDIDATES_PER_RELATED_POST);
+
+        let similar_map = posts
+            .par_iter()
+            .zip(neighbours)
+            .enumerate()
+            .map(|(i, (post, scored))| {
+                let related = select_related(
+                    posts,
+                    i,
+                    &scored,
+                    |a, b| cosine_similarity(&embeddings[a], &embeddings[b]),
+                    selection,
+                );
+                (post.id.clone(), related)
+            })
             .collect();
 
-        let embed_start = Instant::now();
-        let embeddings = model.encode(&texts);
         println!(
-            "  Encoded {} posts in {:.2}s",
-            posts.len(),
-            embed_start.elapsed().as_secs_f64()
+            "✓ Computed smart similarity in {:.2}s",
+            start.elapsed().as_secs_f64()
         );
 
-        let mut similar_map = HashMap::with_capacity(posts.len());
+        Ok(SmartSimilarity {
+            similar_map,
+            embeddings,
+        })
+    }
 
-        for (i, post) in posts.iter().enumerate() {
-            let mut scored: Vec<(usize, f32)> = (0..posts.len())
-                .filter(|&j| j != i)
-                .map(|j| (j, cosine_similarity(&embeddings[i], &embeddings[j])))
-                .collect();
+    pub fn compute_post_neighbours(
+        posts: &[Post],
+        options: &SmartSimilarOptions,
+        k: usize,
+    ) -> Result<Vec<Vec<(usize, f32)>>> {
+        let embeddings = post_embeddings(posts, options)?;
+        Ok(nearest_neighbours(&embeddings, k))
+    }
 
-            scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            scored.truncate(TOP_SIMILAR_COUNT);
+    /// One unit-length embedding per post, in the order of `posts`.
+    fn post_embeddings(posts: &[Post], options: &SmartSimilarOptions) -> Result<Vec<Vec<f32>>> {
+        let source = resolve_model_source(options.model_path.as_deref())?;
 
-            let summaries = scored
-                .into_iter()
-                .map(|(j, _)| post_to_summary(&posts[j]))
-                .collect();
+        let post_texts: Vec<PostTexts> = posts.iter().map(build_post_texts).collect();
+        let texts: Vec<String> = post_texts
+            .iter()
+            .flat_map(|p| {
+                p.sections
+                    .iter()
+                    .flatten()
+                    .chain(std::iter::once(&p.title))
+                    .chain(p.tags.iter())
+                    .cloned()
+            })
+            .collect();
 
-            similar_map.insert(post.id.clone(), summaries);
+        let vectors = embed_texts(&source, &options.cache_dir, &texts)?;
+        let embeddings: Vec<Vec<f32>> = post_texts
+            .iter()
+            .map(|p| combine_post_embedding(p, &vectors, options))
+            .collect();
+        Ok(embeddings)
+    }
+
+    fn resolve_model_source(model_path: Option<&Path>) -> Result<ModelSource> {
+        if let Some(path) = model_path {
+            if !path.is_dir() {
+                anyhow::bail!("--model-path {} is not a directory", path.display());
+            }
+            if let Some(file) = MODEL_FILES.iter().find(|f| !path.join(f).is_file()) {
+                anyhow::bail!(
+                    "--model-path {} does not contain {} (expected {})",
+                    path.display(),
+                    file,
+                    MODEL_FILES.join(", ")
+                );
+            }
+            let path = path.canonicalize()?;
+            return Ok(ModelSource {
+                id: format!("local:{}", path.display()),
+                location: path.display().to_string(),
+                needs_download: false,
+            });
         }
 
-        println!(
-            "✓ Computed smart similarity in {:.2}s",
-            start.elapsed().as_secs_f64()
-        );
+        match cached_hub_snapshot(MODEL_NAME) {
+            Some(snapshot) => Ok(ModelSource {
+                id: MODEL_NAME.to_string(),
+                location: snapshot.display().to_string(),
+                needs_download: false,
+            }),
+            None => Ok(ModelSource {
+                id: MODEL_NAME.to_string(),
+                location: MODEL_NAME.to_string(),
+                needs_download: true,
+            }),
+        }
+    }
+
+    /// Finds a complete snapshot of `repo` in the local Hugging Face cache,
+    /// honouring `HF_HUB_CACHE` and `HF_HOME` like the Python tooling does.
+    fn cached_hub_snapshot(repo: &str) -> Option<PathBuf> {
+        let hub_dir = env::var_os("HF_HUB_CACHE")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HF_HOME").map(|home| PathBuf::from(home).join("hub")))
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache/huggingface/hub"))
+            })?;
+        let repo_dir = hub_dir.join(format!("models--{}", repo.replace('/', "--")));
+        let revision = fs::read_to_string(repo_dir.join("refs/main")).ok()?;
+        let snapshot = repo_dir.join("snapshots").join(revision.trim());
+
+        MODEL_FILES
+            .iter()
+            .all(|f| snapshot.join(f).is_file())
+            .then_some(snapshot)
+    }
+
+    fn load_model(source: &ModelSource) -> Result<StaticModel> {
+        let start = Instant::now();
+        println!("Loading embedding model ({})...", source.id);
 
-        Ok(similar_map)
+        let model = if source.needs_download {
+            StaticModel::from_pretrained(&source.location, None, None, None).with_context(|| {
+                format!(
+                    "Could not download embedding model '{}' and no local copy was found. \
+                     If this machine is offline, fetch the model once elsewhere \
+                     (e.g. `huggingface-cli download {}`) and pass --model-path <dir> \
+                     pointing at a directory with {}",
+                    MODEL_NAME,
+                    MODEL_NAME,
+                    MODEL_FILES.join(", ")
+                )
+            })?
+        } else {
+            StaticModel::from_pretrained(&source.location, None, None, None).with_context(|| {
+                format!("Could not load embedding model from {}", source.location)
+            })?
+        };
+
+        println!("  Model loaded in {:.2}s", start.elapsed().as_secs_f64());
+        Ok(model)
     }
 
-    fn build_embed_text(title: &str, raw_markdown: &str) -> String {
-        let truncated: String = raw_markdown.chars().take(EMBED_TEXT_CHAR_LIMIT).collect();
-        format!("{}. {}", title, truncated)
+    /// Embeds `texts`, taking unchanged ones from the on-disk cache. The model
+    /// is only loaded when at least one text changed since the last build.
+    fn embed_texts(
+        source: &ModelSource,
+        cache_dir: &Path,
+        texts: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>> {
+        let mut cache = EmbeddingCache::load(&cache_dir.join(EMBEDDING_CACHE_FILE));
+        let mut vectors = HashMap::with_capacity(texts.len());
+        let mut seen = HashSet::with_capacity(texts.len());
+        let mut missing: Vec<String> = Vec::new();
+
+        for text in texts {
+            if !seen.insert(text) {
+                continue;
+            }
+            match cache.get(&cache_key(&source.id, text)) {
+                Some(vector) => {
+                    vectors.insert(text.clone(), vector);
+                }
+                None => missing.push(text.clone()),
+            }
+        }
+
+        if missing.is_empty() {
+            println!("  All {} embeddings loaded from cache", vectors.len());
+        } else {
+            let model = load_model(source)?;
+
+            let embed_start = Instant::now();
+            let encoded = model.encode(&missing);
+            println!(
+                "  Encoded {} of {} texts in {:.2}s",
+                missing.len(),
+                missing.len() + vectors.len(),
+                embed_start.elapsed().as_secs_f64()
+            );
+
+            for (text, vector) in missing.into_iter().zip(encoded) {
+                cache.insert(cache_key(&source.id, &text), vector.clone());
+                vectors.insert(text, vector);
+            }
+        }
+        cache.save()?;
+
+        Ok(vectors)
     }
 
-    fn post_to_summary(post: &Post) -> PostSummary {
-        PostSummary {
-            id: post.id.clone(),
+    fn build_post_texts(post: &Post) -> PostTexts {
+        PostTexts {
+            sections: prose_sections(&post.content_html)
+                .iter()
+                .map(chunk_section)
+                .collect(),
             title: post.title.clone(),
-            date: post.date.clone(),
-            tags: post.tags.clone(),
-            icon: post.icon.clone(),
+            tags: (!post.tags.is_empty()).then(|| post.tags.join(", ")),
+        }
+    }
+
+    /// Splits a section into overlapping word windows, each prefixed with the
+    /// section heading so a chunk keeps the context it was written under.
+    fn chunk_section(section: &ProseSection) -> Vec<String> {
+        let words: Vec<&str> = section.text.split_whitespace().collect();
+        let stride = CHUNK_WORDS - CHUNK_OVERLAP_WORDS;
+        let mut chunks = Vec::new();
+        let mut begin = 0;
+
+        loop {
+            let end = (begin + CHUNK_WORDS).min(words.len());
+            let body = words[begin..end].join(" ");
+            if section.heading.is_empty() {
+                chunks.push(body);
+            } else {
+                chunks.push(format!("{}. {}", section.heading, body));
+            }
+            if end == words.len() {
+                break;
+            }
+            begin += stride;
         }
+
+        chunks
+    }
+
+    fn combine_post_embedding(
+        texts: &PostTexts,
+        vectors: &HashMap<String, Vec<f32>>,
+        options: &SmartSimilarOptions,
+    ) -> Vec<f32> {
+        let section_vectors: Vec<Vec<&[f32]>> = texts
+            .sections
+            .iter()
+            .map(|chunks| chunks.iter().map(|c| vectors[c].as_slice()).collect())
+            .collect();
+        let title = vectors[&texts.title].as_slice();
+
+        let mut parts: Vec<(Vec<f32>, f32)> = Vec::new();
+        if let Some(body) = pool_chunks(&section_vectors, options.pooling) {
+            parts.push((body, 1.0));
+        }
+        parts.push((normalized(title), options.title_weight));
+        if let Some(tags) = &texts.tags {
+            parts.push((normalized(&vectors[tags]), options.tag_weight));
+        }
+
+        let mut combined = vec![0.0; title.len()];
+        for (vector, weight) in &parts {
+            for (c, v) in combined.iter_mut().zip(vector) {
+                *c += weight * v;
+            }
+        }
+        normalized(&combined)
+    }
+
+    /// Pools chunk vectors into one normalized body vector, or `None` for a post without prose.
+    fn pool_chunks(sections: &[Vec<&[f32]>], pooling: ChunkPooling) -> Option<Vec<f32>> {
+        let pooled = match pooling {
+            ChunkPooling::Mean => mean(sections.iter().flatten().copied()),
+            ChunkPooling::Section => {
+                let section_means: Vec<Vec<f32>> = sections
+                    .iter()
+                    .filter_map(|chunks| mean(chunks.iter().copied()))
+                    .collect();
+                mean(section_means.iter().map(Vec::as_slice))
+            }
+        }?;
+        Some(normalized(&pooled))
+    }
+
+    fn mean<'a>(vectors: impl Iterator<Item = &'a [f32]>) -> Option<Vec<f32>> {
+        let mut sum: Option<Vec<f32>> = None;
+        let mut count = 0;
+        for vector in vectors {
+            let sum = sum.get_or_insert_with(|| vec![0.0; vector.len()]);
+            for (s, v) in sum.iter_mut().zip(vector) {
+                *s += v;
+            }
+            count += 1;
+        }
+        sum.map(|sum| sum.into_iter().map(|s| s / count as f32).collect())
+    }
+
+    fn normalized(vector: &[f32]) -> Vec<f32> {
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            return vector.to_vec();
+        }
+        vector.iter().map(|x| x / norm).collect()
     }
 
     fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
@@ -87,17 +484,154 @@ mod inner {
         }
         dot / denominator
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{chunk_section, pool_chunks, CHUNK_OVERLAP_WORDS, CHUNK_WORDS};
+        use crate::page_generation::ProseSection;
+        use crate::similarity::ChunkPooling;
+
+        #[test]
+        fn chunks_overlap_and_cover_the_whole_section() {
+            let words: Vec<String> = (0..CHUNK_WORDS * 2).map(|i| format!("w{}", i)).collect();
+            let section = ProseSection {
+                heading: "Heading".to_string(),
+                text: words.join(" "),
+            };
+
+            let chunks = chunk_section(&section);
+
+            assert_eq!(chunks.len(), 3);
+            assert!(chunks.iter().all(|c| c.starts_with("Heading. ")));
+            let second_start = CHUNK_WORDS - CHUNK_OVERLAP_WORDS;
+            assert!(chunks[1].starts_with(&format!("Heading. w{} ", second_start)));
+            assert!(chunks[2].ends_with(&format!("w{}", CHUNK_WORDS * 2 - 1)));
+        }
+
+        #[test]
+        fn section_pooling_weighs_sections_equally() {
+            let long: &[f32] = &[1.0, 0.0];
+            let short: &[f32] = &[0.0, 1.0];
+            let sections = vec![vec![long, long, long], vec![short]];
+
+            let mean = pool_chunks(&sections, ChunkPooling::Mean).unwrap();
+            let section = pool_chunks(&sections, ChunkPooling::Section).unwrap();
+
+            assert!(mean[0] > mean[1]);
+            assert!((section[0] - section[1]).abs() < 1e-6);
+        }
+
+        #[test]
+        fn pooling_without_chunks_is_none() {
+            assert_eq!(pool_chunks(&[], ChunkPooling::Mean), None);
+        }
+    }
 }
 
 #[cfg(feature = "smart-similar")]
-pub fn compute_similar_posts(posts: &[Post]) -> anyhow::Result<HashMap<String, Vec<PostSummary>>> {
-    inner::compute_similar_posts(posts)
+pub fn compute_similar_posts(
+    posts: &[Post],
+    options: &SmartSimilarOptions,
+    selection: &RelatedSelection,
+) -> anyhow::Result<SmartSimilarity> {
+    inner::compute_similar_posts(posts, options, selection)
+}
+
+/// The `k` nearest neighbours of every post by embedding, most similar first.
+#[cfg(feature = "smart-similar")]
+pub fn compute_post_neighbours(
+    posts: &[Post],
+    options: &SmartSimilarOptions,
+    k: usize,
+) -> anyhow::Result<Vec<Vec<(usize, f32)>>> {
+    inner::compute_post_neighbours(posts, options, k)
 }
 
 #[cfg(not(feature = "smart-similar"))]
-pub fn compute_similar_posts(_posts: &[Post]) -> anyhow::Result<HashMap<String, Vec<PostSummary>>> {
-    anyhow::bail!(
+pub fn compute_similar_posts(
+    _posts: &[Post],
+    _options: &SmartSimilarOptions,
+    _selection: &RelatedSelection,
+) -> anyhow::Result<SmartSimilarity> {
+    Err(feature_disabled())
+}
+
+#[cfg(not(feature = "smart-similar"))]
+pub fn compute_post_neighbours(
+    _posts: &[Post],
+    _options: &SmartSimilarOptions,
+    _k: usize,
+) -> anyhow::Result<Vec<Vec<(usize, f32)>>> {
+    Err(feature_disabled())
+}
+
+#[cfg(not(feature = "smart-similar"))]
+fn feature_disabled() -> anyhow::Error {
+    anyhow::anyhow!(
         "--smart-similar requires the 'smart-similar' feature. \
          Rebuild with: cargo build --features smart-similar"
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{select_related, RelatedSelection};
+    use crate::page_generation::Post;
+
+    fn post(id: &str, related: &[&str]) -> Post {
+        Post {
+            id: id.to_string(),
+            title: id.to_string(),
+            date: "2024-01-01".to_string(),
+            date_modified: "2024-01-01".to_string(),
+            tags: Vec::new(),
+            icon: None,
+            content_html: String::new(),
+            related: related.iter().map(|r| r.to_string()).collect(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    fn ids(posts: &[Post], scored: &[(usize, f32)], diversity: f32) -> Vec<String> {
+        // Posts 1 and 2 are near duplicates of each other
+        let pair_score = |a: usize, b: usize| if a + b == 3 { 0.95 } else { 0.1 };
+        let selection = RelatedSelection {
+            count: 2,
+            min_score: 0.3,
+            diversity,
+        };
+        select_related(posts, 0, scored, pair_score, &selection)
+            .into_iter()
+            .map(|summary| summary.id)
+            .collect()
+    }
+
+    #[test]
+    fn pinned_posts_come_first_and_low_scores_are_dropped() {
+        let posts = vec![
+            post("a", &["d", "missing"]),
+            post("b", &[]),
+            post("c", &[]),
+            post("d", &[]),
+        ];
+        let scored = [(1, 0.9), (2, 0.2), (3, 0.1)];
+
+        assert_eq!(ids(&posts, &scored, 1.0), vec!["d", "b"]);
+    }
+
+    #[test]
+    fn diversity_skips_near_duplicates() {
+        let posts = vec![
+            post("a", &[]),
+            post("b", &[]),
+            post("c", &[]),
+            post("d", &[]),
+        ];
+        let scored = [(1, 0.9), (2, 0.85), (3, 0.6)];
+
+        assert_eq!(ids(&posts, &scored, 1.0), vec!["b", "c"]);
+        assert_eq!(ids(&posts, &scored, 0.7), vec!["b", "d"]);
+    }
+}
@@ -1,79 +1,233 @@
+//! Writes `sitemap.xml` and `robots.txt`.
+//!
+//! The sitemap lists every HTML page in the output directory under its
+//! canonical URL, except those marked `noindex`, so it has to run after all
+//! other pages are generated.
+//! Posts carry their last modification date and image entries.
+
 use anyhow::Result;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
+use walkdir::WalkDir;
 
+use crate::config::RobotsConfig;
+use crate::feed_generation::escape_xml;
 use crate::page_generation::Post;
+use crate::robots::{is_noindex_page, render_robots_txt};
+use crate::urls::{absolute_url, SiteUrls, BASE_URL};
+
+/// URLs per sitemap allowed by the sitemap protocol; beyond this the URLs
+/// are split across several sitemaps and `sitemap.xml` becomes their index.
+const MAX_URLS_PER_SITEMAP: usize = 50_000;
+/// Image entries allowed per URL by the image sitemap extension.
+const MAX_IMAGES_PER_PAGE: usize = 1_000;
 
-const BASE_URL: &str = "https://seanpedersen.github.io";
+static CANONICAL_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)<link\b[^>]*\brel\s*=\s*"canonical"[^>]*>"#).unwrap());
+static HREF_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)\bhref\s*=\s*"([^"]*)""#).unwrap());
+static IMG_SRC_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)<img\b[^>]*?\bsrc\s*=\s*"([^"]*)""#).unwrap());
+
+struct SitemapPage {
+    loc: String,
+    /// Date of the last content change, `YYYY-MM-DD`, if known.
+    lastmod: Option<String>,
+    images: Vec<String>,
+}
 
-pub fn build_sitemap_and_extras(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
+pub fn build_sitemap_and_extras(
+    out_dir: &Path,
+    posts: &Arc<Vec<Post>>,
+    urls: &SiteUrls,
+    robots: &RobotsConfig,
+) -> Result<()> {
     let start = Instant::now();
 
-    generate_sitemap(out_dir, posts)?;
-    generate_robots_txt(out_dir)?;
-    generate_llms_txt(out_dir, posts)?;
+    let pages = collect_pages(out_dir, posts, urls)?;
+    let sitemaps = write_sitemaps(out_dir, &pages)?;
+    fs::write(out_dir.join("robots.txt"), render_robots_txt(robots)?)?;
 
     println!(
-        "✓ Generated sitemap.xml, robots.txt, llms.txt in {:.2}s",
+        "✓ Generated sitemap.xml ({} URLs in {} sitemaps) and robots.txt in {:.2}s",
+        pages.len(),
+        sitemaps,
         start.elapsed().as_secs_f64()
     );
     Ok(())
 }
 
-fn generate_sitemap(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
-    let path = out_dir.join("sitemap.xml");
-    let mut file = BufWriter::new(File::create(path)?);
-
-    write!(
-        file,
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
-  <url>
-    <loc>{BASE_URL}/</loc>
-    <changefreq>weekly</changefreq>
-    <priority>1.0</priority>
-  </url>
-"#
-    )?;
+/// Emitted pages and their metadata, sorted by URL.
+fn collect_pages(out_dir: &Path, posts: &[Post], urls: &SiteUrls) -> Result<Vec<SitemapPage>> {
+    let posts_by_file: HashMap<PathBuf, &Post> = posts
+        .iter()
+        .map(|post| (Path::new("posts").join(&post.id).join("index.html"), post))
+        .collect();
+    let newest_modification = posts.iter().map(|p| p.date_modified.as_str()).max();
+
+    let mut pages = Vec::new();
+    for entry in WalkDir::new(out_dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some("html")
+        {
+            continue;
+        }
+        let relative = path.strip_prefix(out_dir)?;
+        let html = fs::read_to_string(path)?;
+        if is_noindex_page(&html) {
+            continue;
+        }
+        let loc = CANONICAL_RE
+            .find(&html)
+            .and_then(|link| HREF_RE.captures(link.as_str()))
+            .map(|caps| caps[1].to_string())
+            .unwrap_or_else(|| urls.absolute(&urls.for_file(relative)));
 
-    for post in posts.iter() {
-        write!(
-            file,
-            "  <url>\n    <loc>{BASE_URL}/posts/{}/</loc>\n    <lastmod>{}</lastmod>\n    <changefreq>monthly</changefreq>\n    <priority>0.8</priority>\n  </url>\n",
-            post.id, post.date
-        )?;
+        let (lastmod, images) = match posts_by_file.get(relative) {
+            Some(post) => (Some(post.date_modified.clone()), post_images(post, urls)),
+            None if relative == Path::new("index.html") => {
+                (newest_modification.map(str::to_string), Vec::new())
+            }
+            None => (None, Vec::new()),
+        };
+        pages.push(SitemapPage {
+            loc,
+            lastmod,
+            images,
+        });
     }
 
-    write!(file, "</urlset>")?;
-    Ok(())
+    pages.sort_by(|a, b| a.loc.cmp(&b.loc));
+    Ok(pages)
 }
 
-fn generate_robots_txt(out_dir: &Path) -> Result<()> {
-    let path = out_dir.join("robots.txt");
-    let mut file = BufWriter::new(File::create(path)?);
+/// Absolute URLs of the images in a post, in order of appearance.
+pub fn post_images(post: &Post, urls: &SiteUrls) -> Vec<String> {
+    let page_url = urls.post_base(&post.id);
+    let mut images: Vec<String> = Vec::new();
+    for caps in IMG_SRC_RE.captures_iter(&post.content_html) {
+        let src = caps[1].trim();
+        if src.is_empty() || src.starts_with("data:") {
+            continue;
+        }
+        let url = absolute_url(src, &page_url);
+        if !images.contains(&url) {
+            images.push(url);
+        }
+    }
+    images.truncate(MAX_IMAGES_PER_PAGE);
+    images
+}
+
+/// Writes `sitemap.xml`, or, past the protocol's URL limit, numbered
+/// sitemaps listed by a `sitemap.xml` sitemap index.
+fn write_sitemaps(out_dir: &Path, pages: &[SitemapPage]) -> Result<usize> {
+    if pages.len() <= MAX_URLS_PER_SITEMAP {
+        fs::write(out_dir.join("sitemap.xml"), render_urlset(pages))?;
+        return Ok(1);
+    }
 
-    write!(
-        file,
-        "User-agent: *\nAllow: /\n\nSitemap: {BASE_URL}/sitemap.xml\n"
+    let mut sitemap_urls = Vec::new();
+    for (i, chunk) in pages.chunks(MAX_URLS_PER_SITEMAP).enumerate() {
+        let file_name = format!("sitemap-{}.xml", i + 1);
+        fs::write(out_dir.join(&file_name), render_urlset(chunk))?;
+        sitemap_urls.push(format!("{}/{}", BASE_URL, file_name));
+    }
+    fs::write(
+        out_dir.join("sitemap.xml"),
+        render_sitemap_index(&sitemap_urls),
     )?;
-    Ok(())
+    Ok(sitemap_urls.len())
 }
 
-fn generate_llms_txt(out_dir: &Path, posts: &Arc<Vec<Post>>) -> Result<()> {
-    let path = out_dir.join("llms.txt");
-    let mut file = BufWriter::new(File::create(path)?);
+fn render_urlset(pages: &[SitemapPage]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\"",
+    );
+    if pages.iter().any(|page| !page.images.is_empty()) {
+        xml.push_str(" xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\"");
+    }
+    xml.push_str(">\n");
 
-    write!(
-        file,
-        "# Sean Pedersen's Blog\n\n> Personal blog exploring Machine Learning, Data Privacy, Cybernetics and Memetics.\n\n## Posts\n\n"
-    )?;
+    for page in pages {
+        xml.push_str(&format!(
+            "  <url>\n    <loc>{}</loc>\n",
+            escape_xml(&page.loc)
+        ));
+        if let Some(lastmod) = &page.lastmod {
+            xml.push_str(&format!("    <lastmod>{}</lastmod>\n", escape_xml(lastmod)));
+        }
+        for image in &page.images {
+            xml.push_str(&format!(
+                "    <image:image>\n      <image:loc>{}</image:loc>\n    </image:image>\n",
+                escape_xml(image)
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+
+    xml.push_str("</urlset>\n");
+    xml
+}
 
-    for post in posts.iter() {
-        writeln!(file, "- [{}]({}/posts/{}/)", post.title, BASE_URL, post.id)?;
+fn render_sitemap_index(sitemap_urls: &[String]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for url in sitemap_urls {
+        xml.push_str(&format!(
+            "  <sitemap>\n    <loc>{}</loc>\n  </sitemap>\n",
+            escape_xml(url)
+        ));
     }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::{render_sitemap_index, render_urlset, SitemapPage};
+
+    #[test]
+    fn urlset_escapes_urls_and_lists_images() {
+        let pages = [
+            SitemapPage {
+                loc: "https://example.com/?a=1&b=2".to_string(),
+                lastmod: None,
+                images: Vec::new(),
+            },
+            SitemapPage {
+                loc: "https://example.com/posts/x".to_string(),
+                lastmod: Some("2024-05-01".to_string()),
+                images: vec!["https://example.com/images/x.webp".to_string()],
+            },
+        ];
+
+        let xml = render_urlset(&pages);
+
+        assert!(xml.contains(r#"xmlns:image="http://www.google.com/schemas/sitemap-image/1.1""#));
+        assert!(xml.contains("<loc>https://example.com/?a=1&amp;b=2</loc>\n  </url>"));
+        assert!(xml.contains(
+            "<loc>https://example.com/posts/x</loc>\n    <lastmod>2024-05-01</lastmod>\n    <image:image>\n      <image:loc>https://example.com/images/x.webp</image:loc>"
+        ));
+    }
+
+    #[test]
+    fn sitemap_index_lists_each_sitemap() {
+        let xml = render_sitemap_index(&[
+            "https://example.com/sitemap-1.xml".to_string(),
+            "https://example.com/sitemap-2.xml".to_string(),
+        ]);
+
+        assert!(
+            xml.contains("<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">")
+        );
+        assert_eq!(xml.matches("<sitemap>").count(), 2);
+        assert!(xml.contains("<loc>https://example.com/sitemap-2.xml</loc>"));
+    }
 }
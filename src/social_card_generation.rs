@@ -0,0 +1,257 @@
+//! Renders a 1200×630 Open Graph card for every post without a cover image:
+//! the post's title, date, tags and icon laid out by the SVG template in
+//! `website/social/card.svg` and rasterized to PNG with resvg.
+//!
+//! Rasterizing is by far the slowest step, so PNGs are cached in
+//! `social-cards/` of the cache directory under a hash of the rendered SVG
+//! and the icon it embeds; a card is only rendered again when one of them
+//! changes. Text is set in the first installed font of the template's font
+//! list.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use resvg::{tiny_skia, usvg};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tera::Tera;
+
+use crate::page_generation::{format_date, Post};
+use crate::urls::{absolute_url, SiteUrls};
+
+pub const CARD_WIDTH: u32 = 1200;
+pub const CARD_HEIGHT: u32 = 630;
+const TEMPLATE_PATH: &str = "website/social/card.svg";
+/// Directory `<image>` references in the template resolve against.
+const RESOURCES_DIR: &str = "website";
+/// Output directory of the cards, relative to the site root.
+pub const CARD_DIR: &str = "images/social";
+/// Cache of rendered cards, relative to the cache directory.
+const CACHE_SUBDIR: &str = "social-cards";
+const SITE_NAME: &str = "Sean's Blog";
+
+/// Width available to the title, between the left and right margins.
+const TITLE_WIDTH: f32 = 1040.0;
+/// Top of the title block, below the site name and icon.
+const TITLE_TOP: f32 = 230.0;
+/// Font sizes tried in turn, each with the most lines it may take, until
+/// the title fits. Titles too long for the last one are cut short.
+const TITLE_LAYOUTS: &[(f32, usize)] = &[(72.0, 3), (60.0, 4)];
+const LINE_HEIGHT: f32 = 1.2;
+
+static FONTS: Lazy<Arc<usvg::fontdb::Database>> = Lazy::new(|| {
+    let mut fonts = usvg::fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+
+#[derive(Serialize)]
+struct CardContext<'a> {
+    width: u32,
+    height: u32,
+    site_name: &'static str,
+    title_lines: Vec<String>,
+    title_size: f32,
+    title_y: f32,
+    title_line_height: f32,
+    date: String,
+    tags: &'a [String],
+    /// Icon path relative to [`RESOURCES_DIR`].
+    icon: Option<String>,
+}
+
+/// Absolute URL of the image social previews of `post` show: its cover
+/// image if it sets one, otherwise its generated card.
+pub fn social_image_url(post: &Post, urls: &SiteUrls) -> String {
+    match &post.image {
+        Some(image) => absolute_url(image, &urls.post_base(&post.id)),
+        None => urls.absolute(&format!("/{}/{}.png", CARD_DIR, post.id)),
+    }
+}
+
+pub fn build_social_cards(out_dir: &Path, cache_dir: &Path, posts: &[Post]) -> Result<()> {
+    let start = Instant::now();
+
+    let mut tera = Tera::default();
+    tera.add_template_file(TEMPLATE_PATH, Some("card.svg"))?;
+    tera.autoescape_on(vec![".svg"]);
+
+    let cards_dir = out_dir.join(CARD_DIR);
+    let cache_dir = cache_dir.join(CACHE_SUBDIR);
+    fs::create_dir_all(&cards_dir)?;
+    fs::create_dir_all(&cache_dir)?;
+
+    let cards: Vec<(String, bool)> = posts
+        .par_iter()
+        .filter(|post| post.image.is_none())
+        .map(|post| -> Result<(String, bool)> {
+            let (svg, icon) = render_card_svg(&tera, post)?;
+            let key = cache_key(&svg, icon.as_deref())?;
+            let cached = cache_dir.join(format!("{}.png", key));
+            let hit = cached.is_file();
+            if !hit {
+                let png = rasterize(&svg).with_context(|| {
+                    format!("Failed to render the social card of '{}'", post.id)
+                })?;
+                fs::write(&cached, png)?;
+            }
+            fs::copy(&cached, cards_dir.join(format!("{}.png", post.id)))?;
+            Ok((key, hit))
+        })
+        .collect::<Result<_>>()?;
+
+    // Drop cards of posts that changed or no longer exist
+    let used: HashSet<String> = cards
+        .iter()
+        .map(|(key, _)| format!("{}.png", key))
+        .collect();
+    for entry in fs::read_dir(&cache_dir)? {
+        let entry = entry?;
+        if !used.contains(entry.file_name().to_string_lossy().as_ref()) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    println!(
+        "✓ Generated {} social cards ({} cached) in {:.2}s",
+        cards.len(),
+        cards.iter().filter(|(_, hit)| *hit).count(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// The card SVG of `post`, and the icon file it refers to, if any.
+fn render_card_svg(tera: &Tera, post: &Post) -> Result<(String, Option<PathBuf>)> {
+    let icon = post
+        .icon
+        .as_deref()
+        .map(|icon| icon.trim_start_matches('/'))
+        .filter(|icon| Path::new(RESOURCES_DIR).join(icon).is_file());
+
+    let (title_size, title_lines) = layout_title(&post.title);
+    let context = CardContext {
+        width: CARD_WIDTH,
+        height: CARD_HEIGHT,
+        site_name: SITE_NAME,
+        title_lines,
+        title_size,
+        title_y: TITLE_TOP + title_size,
+        title_line_height: title_size * LINE_HEIGHT,
+        date: format_date(&post.date),
+        tags: &post.tags,
+        icon: icon.map(str::to_string),
+    };
+    let svg = tera.render("card.svg", &tera::Context::from_serialize(&context)?)?;
+    Ok((svg, icon.map(|icon| Path::new(RESOURCES_DIR).join(icon))))
+}
+
+fn cache_key(svg: &str, icon: Option<&Path>) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(svg.as_bytes());
+    if let Some(icon) = icon {
+        hasher.update(fs::read(icon)?);
+    }
+    Ok(format!("{:x}", hasher.finalize())[..16].to_string())
+}
+
+fn rasterize(svg: &str) -> Result<Vec<u8>> {
+    let options = usvg::Options {
+        resources_dir: Some(PathBuf::from(RESOURCES_DIR)),
+        fontdb: FONTS.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let mut pixmap =
+        tiny_skia::Pixmap::new(CARD_WIDTH, CARD_HEIGHT).context("Invalid social card size")?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    Ok(pixmap.encode_png()?)
+}
+
+/// Picks the largest title size at which `title` fits, and wraps it.
+fn layout_title(title: &str) -> (f32, Vec<String>) {
+    for &(size, max_lines) in TITLE_LAYOUTS {
+        let lines = wrap(title, TITLE_WIDTH / size);
+        if lines.len() <= max_lines {
+            return (size, lines);
+        }
+    }
+
+    let (size, max_lines) = TITLE_LAYOUTS[TITLE_LAYOUTS.len() - 1];
+    let mut lines = wrap(title, TITLE_WIDTH / size);
+    lines.truncate(max_lines);
+    if let Some(last) = lines.last_mut() {
+        while !last.is_empty() && text_width(last) + text_width("…") > TITLE_WIDTH / size {
+            last.pop();
+        }
+        *last = format!("{}…", last.trim_end());
+    }
+    (size, lines)
+}
+
+/// Greedy word wrap to lines at most `max_width` ems wide. A word wider
+/// than a line gets a line of its own.
+fn wrap(text: &str, max_width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && text_width(&line) + text_width(" ") + text_width(word) > max_width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Approximate advance width of `text` in ems, for a bold sans-serif face.
+fn text_width(text: &str) -> f32 {
+    text.chars()
+        .map(|c| match c {
+            ' ' => 0.35,
+            'i' | 'j' | 'l' | 'I' | '.' | ',' | ':' | ';' | '!' | '\'' | '|' => 0.34,
+            'f' | 't' | 'r' | '(' | ')' | '-' => 0.45,
+            'm' | 'w' | 'M' | 'W' => 0.95,
+            c if c.is_uppercase() => 0.76,
+            c if c.is_ascii() => 0.66,
+            _ => 0.8,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layout_title, text_width, wrap, TITLE_LAYOUTS, TITLE_WIDTH};
+
+    #[test]
+    fn titles_wrap_at_word_boundaries() {
+        assert_eq!(
+            wrap("Structure of Neural Embeddings", 8.0),
+            ["Structure of", "Neural", "Embeddings"]
+        );
+        assert_eq!(wrap("Supercalifragilistic", 5.0), ["Supercalifragilistic"]);
+    }
+
+    #[test]
+    fn long_titles_shrink_then_get_cut_short() {
+        let (size, lines) = layout_title("Zipf's Law");
+        assert_eq!((size, lines.len()), (TITLE_LAYOUTS[0].0, 1));
+
+        let long = "word ".repeat(60);
+        let (size, lines) = layout_title(&long);
+        let (last_size, max_lines) = TITLE_LAYOUTS[TITLE_LAYOUTS.len() - 1];
+        assert_eq!((size, lines.len()), (last_size, max_lines));
+        assert!(lines[max_lines - 1].ends_with('…'));
+        assert!(text_width(&lines[max_lines - 1]) <= TITLE_WIDTH / size);
+    }
+}
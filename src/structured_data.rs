@@ -0,0 +1,442 @@
+//! schema.org JSON-LD for search engines: a `BlogPosting` and a
+//! `BreadcrumbList` for each post, and the `WebSite` (with its search
+//! action) and `Blog` for the home page.
+//!
+//! The markup is serialized from typed structs rather than assembled in the
+//! templates, so titles are always escaped correctly, and every object is
+//! checked for its required properties before it is written: a post that
+//! would produce invalid markup fails the build.
+
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::page_generation::{html_to_text, Post, PostSummary};
+use crate::post_generation::extract_excerpt;
+use crate::sitemap_generation::post_images;
+use crate::social_card_generation::social_image_url;
+use crate::urls::SiteUrls;
+
+const CONTEXT: &str = "https://schema.org";
+const SITE_NAME: &str = "Sean Pedersen's Blog";
+const SITE_DESCRIPTION: &str = "Sean Pedersen's Blog - exploring and building Artificial Intelligence, Data Privacy, Cybernetics and Memetics.";
+const AUTHOR_NAME: &str = "Sean Pedersen";
+const LANGUAGE: &str = "en";
+/// Placeholder of the `SearchAction` URL template.
+const SEARCH_TERM: &str = "search_term_string";
+
+#[derive(Serialize)]
+struct Person {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+#[derive(Serialize)]
+struct WebPage {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    #[serde(rename = "@id")]
+    id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlogPosting<'a> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    headline: &'a str,
+    description: String,
+    url: String,
+    date_published: &'a str,
+    date_modified: &'a str,
+    author: Person,
+    publisher: Person,
+    main_entity_of_page: WebPage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keywords: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    article_section: Option<&'a str>,
+    word_count: usize,
+    image: Vec<String>,
+    in_language: &'static str,
+}
+
+#[derive(Serialize)]
+struct BreadcrumbList {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    #[serde(rename = "itemListElement")]
+    items: Vec<ListItem>,
+}
+
+#[derive(Serialize)]
+struct ListItem {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    position: usize,
+    name: String,
+    /// Omitted for the last crumb, the page itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebSite {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    name: &'static str,
+    url: String,
+    description: &'static str,
+    in_language: &'static str,
+    author: Person,
+    potential_action: SearchAction,
+}
+
+#[derive(Serialize)]
+struct SearchAction {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    target: EntryPoint,
+    #[serde(rename = "query-input")]
+    query_input: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryPoint {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    url_template: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Blog<'a> {
+    #[serde(rename = "@context")]
+    context: &'static str,
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    name: &'static str,
+    url: String,
+    description: &'static str,
+    in_language: &'static str,
+    author: Person,
+    publisher: Person,
+    blog_post: Vec<BlogPostingReference<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlogPostingReference<'a> {
+    #[serde(rename = "@type")]
+    kind: &'static str,
+    headline: &'a str,
+    url: String,
+    date_published: &'a str,
+}
+
+/// JSON-LD of a post page: its `BlogPosting` and `BreadcrumbList`.
+pub fn post_json_ld(post: &Post, urls: &SiteUrls) -> Result<String> {
+    let url = urls.absolute(&urls.post(&post.id));
+
+    // The social preview image first; search engines do not index SVG
+    // images, so icons and diagrams in the content are skipped
+    let mut image = vec![social_image_url(post, urls)];
+    for src in post_images(post, urls) {
+        if !src.ends_with(".svg") && !image.contains(&src) {
+            image.push(src);
+        }
+    }
+
+    let posting = BlogPosting {
+        context: CONTEXT,
+        kind: "BlogPosting",
+        headline: &post.title,
+        description: extract_excerpt(&post.content_html),
+        url: url.clone(),
+        date_published: &post.date,
+        date_modified: &post.date_modified,
+        author: author(urls),
+        publisher: author(urls),
+        main_entity_of_page: WebPage {
+            kind: "WebPage",
+            id: url,
+        },
+        keywords: (!post.tags.is_empty()).then(|| post.tags.join(", ")),
+        article_section: post.tags.first().map(String::as_str),
+        word_count: html_to_text(&post.content_html).split_whitespace().count(),
+        image,
+        in_language: LANGUAGE,
+    };
+    let breadcrumbs = BreadcrumbList {
+        context: CONTEXT,
+        kind: "BreadcrumbList",
+        items: vec![
+            ListItem {
+                kind: "ListItem",
+                position: 1,
+                name: "Home".to_string(),
+                item: Some(urls.absolute("/")),
+            },
+            ListItem {
+                kind: "ListItem",
+                position: 2,
+                name: post.title.clone(),
+                item: None,
+            },
+        ],
+    };
+
+    require("BlogPosting", &post.id, posting.missing_properties())?;
+    require("BreadcrumbList", &post.id, breadcrumbs.missing_properties())?;
+    to_script(&(posting, breadcrumbs))
+}
+
+/// JSON-LD of the home page: the `WebSite` with its search action, and the
+/// `Blog` listing every post.
+pub fn index_json_ld(posts: &[PostSummary], urls: &SiteUrls) -> Result<String> {
+    let home = urls.absolute("/");
+    let website = WebSite {
+        context: CONTEXT,
+        kind: "WebSite",
+        name: SITE_NAME,
+        url: home.clone(),
+        description: SITE_DESCRIPTION,
+        in_language: LANGUAGE,
+        author: author(urls),
+        potential_action: SearchAction {
+            kind: "SearchAction",
+            target: EntryPoint {
+                kind: "EntryPoint",
+                url_template: format!(
+                    "{}?q={{{}}}",
+                    urls.absolute(&urls.directory("search")),
+                    SEARCH_TERM
+                ),
+            },
+            query_input: format!("required name={}", SEARCH_TERM),
+        },
+    };
+    let blog = Blog {
+        context: CONTEXT,
+        kind: "Blog",
+        name: SITE_NAME,
+        url: home,
+        description: SITE_DESCRIPTION,
+        in_language: LANGUAGE,
+        author: author(urls),
+        publisher: author(urls),
+        blog_post: posts
+            .iter()
+            .map(|post| BlogPostingReference {
+                kind: "BlogPosting",
+                headline: &post.title,
+                url: urls.absolute(&urls.post(&post.id)),
+                date_published: &post.date,
+            })
+            .collect(),
+    };
+
+    require("WebSite", "home page", website.missing_properties())?;
+    to_script(&(website, blog))
+}
+
+fn author(urls: &SiteUrls) -> Person {
+    Person {
+        kind: "Person",
+        name: AUTHOR_NAME,
+        url: Some(urls.absolute("/")),
+    }
+}
+
+impl BlogPosting<'_> {
+    fn missing_properties(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.headline.trim().is_empty() {
+            missing.push("headline");
+        }
+        if !is_date(self.date_published) {
+            missing.push("datePublished");
+        }
+        if !is_date(self.date_modified) || self.date_modified < self.date_published {
+            missing.push("dateModified");
+        }
+        if self.image.is_empty() {
+            missing.push("image");
+        }
+        if !self.url.starts_with("https://") {
+            missing.push("url");
+        }
+        missing
+    }
+}
+
+impl BreadcrumbList {
+    fn missing_properties(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.items.is_empty() {
+            missing.push("itemListElement");
+        }
+        let last = self.items.len().saturating_sub(1);
+        for (i, item) in self.items.iter().enumerate() {
+            if item.position != i + 1 {
+                missing.push("position");
+            }
+            if item.name.trim().is_empty() {
+                missing.push("name");
+            }
+            if i < last && item.item.is_none() {
+                missing.push("item");
+            }
+        }
+        missing.dedup();
+        missing
+    }
+}
+
+impl WebSite {
+    fn missing_properties(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if !self.url.starts_with("https://") {
+            missing.push("url");
+        }
+        let placeholder = format!("{{{}}}", SEARCH_TERM);
+        if !self
+            .potential_action
+            .target
+            .url_template
+            .contains(&placeholder)
+        {
+            missing.push("potentialAction.target");
+        }
+        missing
+    }
+}
+
+fn require(kind: &str, subject: &str, missing: Vec<&'static str>) -> Result<()> {
+    if !missing.is_empty() {
+        bail!(
+            "{} of {} is missing or has invalid required properties: {}",
+            kind,
+            subject,
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn is_date(value: &str) -> bool {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+}
+
+/// Serializes JSON-LD for a `<script>` element, which must not contain `</`.
+fn to_script(value: &impl Serialize) -> Result<String> {
+    Ok(serde_json::to_string(value)?.replace("</", "<\\/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_json_ld, post_json_ld};
+    use crate::page_generation::{Post, PostSummary};
+    use crate::urls::{SiteUrls, TrailingSlash};
+    use serde_json::Value;
+
+    fn post(title: &str, date_modified: &str) -> Post {
+        Post {
+            id: "hello".to_string(),
+            title: title.to_string(),
+            date: "2024-03-01".to_string(),
+            date_modified: date_modified.to_string(),
+            tags: vec!["rust".to_string(), "web".to_string()],
+            icon: None,
+            content_html:
+                r#"<p>Three words here</p><img src="chart.png"><img src="/images/icons/x.svg">"#
+                    .to_string(),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn post_gets_blog_posting_and_breadcrumbs() {
+        let urls = SiteUrls::new(TrailingSlash::Never);
+        let script = post_json_ld(&post("Say \"hi\" </script>", "2024-04-01"), &urls).unwrap();
+        assert!(!script.contains("</script>"));
+
+        let json: Value = serde_json::from_str(&script).unwrap();
+        let posting = &json[0];
+        assert_eq!(posting["@type"], "BlogPosting");
+        assert_eq!(posting["headline"], "Say \"hi\" </script>");
+        assert_eq!(posting["url"], "https://seanpedersen.github.io/posts/hello");
+        assert_eq!(posting["dateModified"], "2024-04-01");
+        assert_eq!(posting["keywords"], "rust, web");
+        assert_eq!(posting["wordCount"], 3);
+        assert_eq!(
+            posting["image"],
+            serde_json::json!([
+                "https://seanpedersen.github.io/images/social/hello.png",
+                "https://seanpedersen.github.io/posts/hello/chart.png"
+            ])
+        );
+
+        let breadcrumbs = &json[1];
+        assert_eq!(breadcrumbs["@type"], "BreadcrumbList");
+        assert_eq!(
+            breadcrumbs["itemListElement"][0]["item"],
+            "https://seanpedersen.github.io/"
+        );
+        assert_eq!(breadcrumbs["itemListElement"][1]["position"], 2);
+        assert!(breadcrumbs["itemListElement"][1].get("item").is_none());
+    }
+
+    #[test]
+    fn invalid_posts_fail_validation() {
+        let urls = SiteUrls::new(TrailingSlash::Never);
+        let error = post_json_ld(&post(" ", "2024-02-01"), &urls).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "BlogPosting of hello is missing or has invalid required properties: headline, dateModified"
+        );
+    }
+
+    #[test]
+    fn website_offers_a_search_action() {
+        let urls = SiteUrls::new(TrailingSlash::Always);
+        let posts = [PostSummary {
+            id: "hello".to_string(),
+            title: "Hello".to_string(),
+            date: "2024-03-01".to_string(),
+            tags: Vec::new(),
+            icon: None,
+            score: None,
+        }];
+
+        let json: Value = serde_json::from_str(&index_json_ld(&posts, &urls).unwrap()).unwrap();
+
+        assert_eq!(
+            json[0]["potentialAction"]["target"]["urlTemplate"],
+            "https://seanpedersen.github.io/search/?q={search_term_string}"
+        );
+        assert_eq!(
+            json[0]["potentialAction"]["query-input"],
+            "required name=search_term_string"
+        );
+        assert_eq!(
+            json[1]["blogPost"][0]["url"],
+            "https://seanpedersen.github.io/posts/hello/"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! The `suggest` subcommand: helps curate posts instead of building the site.
+//!
+//! Missing tags are proposed by a similarity-weighted vote of each post's
+//! nearest tagged neighbours, untagged posts are listed, and pairs of posts
+//! similar enough to be near-duplicates are reported. Post sources are only
+//! touched with `--apply`, which adds the suggested tags where the post
+//! already keeps its tags (frontmatter or trailing hashtag line).
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::page_generation::{split_frontmatter, Post};
+
+/// Share of the neighbours' vote weight a tag needs before it is suggested.
+const MIN_VOTE_SHARE: f32 = 0.5;
+/// A tag also needs at least this many neighbours carrying it.
+const MIN_VOTES: usize = 2;
+const MAX_SUGGESTED_TAGS: usize = 3;
+
+static FLOW_TAGS_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^tags:[ \t]*\[(.*?)\][ \t]*$").unwrap());
+static BLOCK_TAGS_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^tags:[ \t]*\n((?:[ \t]*-[^\n]*(?:\n|$))+)").unwrap());
+static HASHTAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"#[a-zA-Z0-9_-]+").unwrap());
+
+pub struct SuggestOptions {
+    pub apply: bool,
+    /// Similarity at or above which two posts are reported as near-duplicates.
+    pub duplicate_threshold: f32,
+    /// Neighbours that vote on the tags of each post.
+    pub neighbours: usize,
+}
+
+/// Prints the curation report for `posts`, given the nearest neighbours of
+/// each post (most similar first), and applies tag suggestions if asked to.
+pub fn run_suggest(
+    posts: &[Post],
+    neighbours: &[Vec<(usize, f32)>],
+    options: &SuggestOptions,
+) -> Result<()> {
+    let suggestions = suggest_tags(posts, neighbours, options.neighbours);
+    let untagged: Vec<&Post> = posts.iter().filter(|p| p.tags.is_empty()).collect();
+    let duplicates = near_duplicates(neighbours, options.duplicate_threshold);
+
+    println!("\nTag suggestions ({} posts):", suggestions.len());
+    for (&i, tags) in &suggestions {
+        let tags: Vec<String> = tags.iter().map(|t| format!("+#{}", t)).collect();
+        println!("  {}: {}", posts[i].id, tags.join(" "));
+    }
+
+    println!("\nUntagged posts ({}):", untagged.len());
+    for post in &untagged {
+        println!("  {}", post.id);
+    }
+
+    println!(
+        "\nNear-duplicates ({} pairs, similarity >= {:.2}):",
+        duplicates.len(),
+        options.duplicate_threshold
+    );
+    for (a, b, score) in &duplicates {
+        println!("  {} <-> {} ({:.2})", posts[*a].id, posts[*b].id, score);
+    }
+
+    if suggestions.is_empty() {
+        return Ok(());
+    }
+    if !options.apply {
+        println!("\nRun with --apply to add the suggested tags to the post sources.");
+        return Ok(());
+    }
+
+    let mut applied = 0;
+    for (&i, tags) in &suggestions {
+        let path = source_path(&posts[i].id)?;
+        let content = fs::read_to_string(&path)?;
+        let is_html = path.extension().and_then(|s| s.to_str()) == Some("html");
+        match add_tags_to_source(&content, tags, is_html) {
+            Some(updated) => {
+                fs::write(&path, updated)?;
+                applied += 1;
+            }
+            None => eprintln!(
+                "Warning: Could not find where {} keeps its tags, skipping",
+                path.display()
+            ),
+        }
+    }
+    println!("\n✓ Added suggested tags to {} posts", applied);
+
+    Ok(())
+}
+
+/// Proposes up to [`MAX_SUGGESTED_TAGS`] tags per post, keyed by post index.
+fn suggest_tags(
+    posts: &[Post],
+    neighbours: &[Vec<(usize, f32)>],
+    k: usize,
+) -> BTreeMap<usize, Vec<String>> {
+    let mut suggestions = BTreeMap::new();
+
+    for (i, post) in posts.iter().enumerate() {
+        let voters: Vec<(usize, f32)> = neighbours[i]
+            .iter()
+            .copied()
+            .filter(|&(j, score)| score > 0.0 && !posts[j].tags.is_empty())
+            .take(k)
+            .collect();
+        let total: f32 = voters.iter().map(|&(_, score)| score).sum();
+        if total == 0.0 {
+            continue;
+        }
+
+        let mut votes: HashMap<&str, (f32, usize)> = HashMap::new();
+        for &(j, score) in &voters {
+            for tag in &posts[j].tags {
+                let vote = votes.entry(tag).or_insert((0.0, 0));
+                vote.0 += score;
+                vote.1 += 1;
+            }
+        }
+
+        let mut tags: Vec<(&str, f32)> = votes
+            .into_iter()
+            .filter(|&(tag, (weight, count))| {
+                count >= MIN_VOTES
+                    && weight / total >= MIN_VOTE_SHARE
+                    && !post.tags.iter().any(|t| t == tag)
+            })
+            .map(|(tag, (weight, _))| (tag, weight))
+            .collect();
+        tags.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        tags.truncate(MAX_SUGGESTED_TAGS);
+
+        if !tags.is_empty() {
+            suggestions.insert(i, tags.into_iter().map(|(t, _)| t.to_string()).collect());
+        }
+    }
+
+    suggestions
+}
+
+/// Pairs `(a, b, similarity)` with `a < b`, most similar first.
+fn near_duplicates(neighbours: &[Vec<(usize, f32)>], threshold: f32) -> Vec<(usize, usize, f32)> {
+    let mut pairs: BTreeMap<(usize, usize), f32> = BTreeMap::new();
+    for (i, scored) in neighbours.iter().enumerate() {
+        for &(j, score) in scored.iter().filter(|&&(_, score)| score >= threshold) {
+            pairs.insert((i.min(j), i.max(j)), score);
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize, f32)> =
+        pairs.into_iter().map(|((a, b), s)| (a, b, s)).collect();
+    pairs.sort_by(|x, y| y.2.total_cmp(&x.2));
+    pairs
+}
+
+fn source_path(id: &str) -> Result<PathBuf> {
+    ["md", "html"]
+        .iter()
+        .map(|ext| Path::new("posts").join(format!("{}.{}", id, ext)))
+        .find(|path| path.is_file())
+        .with_context(|| format!("No source file found for post '{}'", id))
+}
+
+/// Adds `tags` where the post source already declares its tags: a `tags:`
+/// list in the frontmatter, otherwise the trailing hashtag line of a
+/// markdown post. HTML posts without frontmatter tags get a `tags:` entry.
+/// Returns `None` when the tags are declared in a form this cannot edit.
+fn add_tags_to_source(content: &str, tags: &[String], is_html: bool) -> Option<String> {
+    if let Some((yaml, _)) = split_frontmatter(content) {
+        if yaml.lines().any(|line| line.starts_with("tags:")) {
+            let updated = add_tags_to_frontmatter(yaml, tags)?;
+            return Some(content.replacen(yaml, &updated, 1));
+        }
+        if is_html {
+            let updated = format!("{}\ntags: [{}]", yaml, tags.join(", "));
+            return Some(content.replacen(yaml, &updated, 1));
+        }
+    } else if is_html {
+        return None;
+    }
+
+    let hashtags: Vec<String> = tags.iter().map(|t| format!("#{}", t)).collect();
+    let body = content.trim_end();
+    let last_line = body.lines().last().unwrap_or_default();
+    if HASHTAG_RE.is_match(last_line) {
+        Some(format!("{} {}\n", body, hashtags.join(" ")))
+    } else {
+        Some(format!("{}\n\n{}\n", body, hashtags.join(" ")))
+    }
+}
+
+fn add_tags_to_frontmatter(yaml: &str, tags: &[String]) -> Option<String> {
+    if let Some(caps) = FLOW_TAGS_RE.captures(yaml) {
+        let existing = caps[1].trim();
+        let list = if existing.is_empty() {
+            tags.join(", ")
+        } else {
+            format!("{}, {}", existing, tags.join(", "))
+        };
+        return Some(
+            FLOW_TAGS_RE
+                .replace(yaml, format!("tags: [{}]", list))
+                .into_owned(),
+        );
+    }
+
+    let caps = BLOCK_TAGS_RE.captures(yaml)?;
+    let items = caps.get(1)?;
+    let indent: String = items
+        .as_str()
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+    let mut block = items.as_str().to_string();
+    if !block.ends_with('\n') {
+        block.push('\n');
+    }
+    for tag in tags {
+        block.push_str(&format!("{}- {}\n", indent, tag));
+    }
+    if !items.as_str().ends_with('\n') {
+        block.pop();
+    }
+
+    Some(format!(
+        "{}{}{}",
+        &yaml[..items.start()],
+        block,
+        &yaml[items.end()..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tags_to_source, near_duplicates, suggest_tags};
+    use crate::page_generation::Post;
+
+    fn post(id: &str, tags: &[&str]) -> Post {
+        Post {
+            id: id.to_string(),
+            title: id.to_string(),
+            date: "2024-01-01".to_string(),
+            date_modified: "2024-01-01".to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            icon: None,
+            content_html: String::new(),
+            related: Vec::new(),
+            image: None,
+            noindex: false,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn neighbours_vote_for_missing_tags() {
+        let posts = vec![
+            post("new", &[]),
+            post("a", &["rust", "cli"]),
+            post("b", &["rust"]),
+            post("c", &["art"]),
+        ];
+        let neighbours = vec![
+            vec![(1, 0.9), (2, 0.8), (3, 0.3)],
+            vec![(2, 0.8)],
+            vec![(1, 0.8)],
+            vec![(0, 0.3)],
+        ];
+
+        let suggestions = suggest_tags(&posts, &neighbours, 3);
+
+        assert_eq!(suggestions.get(&0), Some(&vec!["rust".to_string()]));
+        assert!(!suggestions.contains_key(&1));
+        assert!(!suggestions.contains_key(&3));
+    }
+
+    #[test]
+    fn near_duplicates_are_reported_once() {
+        let neighbours = vec![vec![(1, 0.97), (2, 0.5)], vec![(0, 0.97)], vec![(0, 0.5)]];
+
+        assert_eq!(near_duplicates(&neighbours, 0.9), vec![(0, 1, 0.97)]);
+    }
+
+    #[test]
+    fn tags_are_added_where_the_post_keeps_them() {
+        let tags = vec!["rust".to_string(), "cli".to_string()];
+
+        let flow = "---\ndate: '2024-01-01'\ntags: [coding]\n---\n\nBody\n";
+        assert_eq!(
+            add_tags_to_source(flow, &tags, false).unwrap(),
+            "---\ndate: '2024-01-01'\ntags: [coding, rust, cli]\n---\n\nBody\n"
+        );
+
+        let block = "---\ntags:\n  - coding\ntitle: X\n---\nBody\n";
+        assert_eq!(
+            add_tags_to_source(block, &tags, false).unwrap(),
+            "---\ntags:\n  - coding\n  - rust\n  - cli\ntitle: X\n---\nBody\n"
+        );
+
+        let hashtags = "# Title\n\nBody\n\n#coding\n";
+        assert_eq!(
+            add_tags_to_source(hashtags, &tags, false).unwrap(),
+            "# Title\n\nBody\n\n#coding #rust #cli\n"
+        );
+
+        let untagged = "# Title\n\nBody\n";
+        assert_eq!(
+            add_tags_to_source(untagged, &tags, false).unwrap(),
+            "# Title\n\nBody\n\n#rust #cli\n"
+        );
+
+        let html = "---\ntitle: X\n---\n<p>Body</p>\n";
+        assert_eq!(
+            add_tags_to_source(html, &tags, true).unwrap(),
+            "---\ntitle: X\ntags: [rust, cli]\n---\n<p>Body</p>\n"
+        );
+    }
+}
@@ -0,0 +1,424 @@
+//! Canonical URLs of the site's pages.
+//!
+//! Every generator builds links through [`SiteUrls`], and templates through
+//! its `url_for` Tera function, so each page is referred to by exactly one
+//! URL in links, feeds, the sitemap and structured data. Whether directory
+//! pages end in a slash is configurable; [`check_internal_links`] fails the
+//! build if any emitted link to a page is not in the canonical form.
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tera::Value;
+use walkdir::WalkDir;
+
+pub const BASE_URL: &str = "https://seanpedersen.github.io";
+
+/// Offending links listed before the report is cut short.
+const MAX_REPORTED_LINKS: usize = 20;
+
+static HREF_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+static NON_MARKUP_BLOCK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<(script|style)\b[^>]*>.*?</(?:script|style)\s*>").unwrap());
+/// Post paths as they turn up in hand-written links: `/posts/<id>` with an
+/// optional trailing slash or source/output file extension.
+static POST_PATH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^/posts/([^/.]+)(?:/|\.html|\.md)?$").unwrap());
+
+/// Whether URLs of directory pages such as `/posts/<id>` end in a slash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrailingSlash {
+    /// `/posts/<id>/`, the address GitHub Pages serves directory pages at.
+    Always,
+    /// `/posts/<id>`, which GitHub Pages redirects to the slashed form.
+    Never,
+}
+
+/// Builds the canonical, root-relative path of every page of the site.
+#[derive(Debug, Clone, Copy)]
+pub struct SiteUrls {
+    trailing_slash: TrailingSlash,
+}
+
+impl SiteUrls {
+    pub fn new(trailing_slash: TrailingSlash) -> Self {
+        Self { trailing_slash }
+    }
+
+    pub fn post(&self, id: &str) -> String {
+        self.directory(&format!("posts/{}", id))
+    }
+
+    /// Absolute URL of the directory post `id` is served from, which
+    /// relative links and images in the post resolve against whatever the
+    /// trailing slash policy.
+    pub fn post_base(&self, id: &str) -> String {
+        format!("{}/posts/{}/", BASE_URL, id)
+    }
+
+    /// Path of the page generated as `<dir>/index.html`.
+    pub fn directory(&self, dir: &str) -> String {
+        let dir = dir.trim_matches('/');
+        match (dir.is_empty(), self.trailing_slash) {
+            (true, _) => "/".to_string(),
+            (false, TrailingSlash::Always) => format!("/{}/", dir),
+            (false, TrailingSlash::Never) => format!("/{}", dir),
+        }
+    }
+
+    /// Path of the page generated at `relative`, a path inside the output
+    /// directory such as `posts/<id>/index.html` or `impressum.html`.
+    pub fn for_file(&self, relative: &Path) -> String {
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        match relative.strip_suffix("index.html") {
+            Some(dir) => self.directory(dir),
+            None => format!("/{}", relative),
+        }
+    }
+
+    /// Canonical form of a root-relative path: directory pages follow the
+    /// trailing slash policy whether or not they are spelled with a slash,
+    /// and links to posts by file name are mapped to the post. Every page
+    /// other than `index.html` has a file extension, so a last segment
+    /// without one names a directory.
+    pub fn canonical_path(&self, path: &str) -> String {
+        if let Some(caps) = POST_PATH_RE.captures(path) {
+            return self.post(&caps[1]);
+        }
+        let dir = match path.strip_suffix("index.html") {
+            Some(dir) if dir.ends_with('/') => dir,
+            _ => path,
+        };
+        let last_segment = dir.rsplit('/').next().unwrap_or_default();
+        if last_segment.contains('.') {
+            path.to_string()
+        } else {
+            self.directory(dir)
+        }
+    }
+
+    pub fn absolute(&self, path: &str) -> String {
+        format!("{}{}", BASE_URL, path)
+    }
+
+    /// Rewrites the internal links of hand-written post HTML to their
+    /// canonical form, keeping any query or fragment.
+    pub fn canonicalize_links(&self, html: &str) -> String {
+        HREF_RE
+            .replace_all(html, |caps: &Captures| {
+                let (value, quote) = match (caps.get(1), caps.get(2)) {
+                    (Some(v), _) => (v.as_str(), '"'),
+                    (_, Some(v)) => (v.as_str(), '\''),
+                    _ => return caps[0].to_string(),
+                };
+                let (origin, rest) = match value.strip_prefix(BASE_URL) {
+                    Some(rest) if rest.starts_with('/') => (BASE_URL, rest),
+                    _ if value.starts_with('/') && !value.starts_with("//") => ("", value),
+                    _ => return caps[0].to_string(),
+                };
+                let (path, suffix) = match rest.find(['?', '#']) {
+                    Some(i) => rest.split_at(i),
+                    None => (rest, ""),
+                };
+                format!(
+                    "href={}{}{}{}{}",
+                    quote,
+                    origin,
+                    self.canonical_path(path),
+                    suffix,
+                    quote
+                )
+            })
+            .into_owned()
+    }
+
+    /// The `url_for` Tera function: `url_for(post=id)`, `url_for(tag=name)`
+    /// or `url_for(path="/search/")`, made absolute with `absolute=true`.
+    pub fn tera_function(self) -> impl tera::Function {
+        move |args: &HashMap<String, Value>| -> tera::Result<Value> {
+            let arg = |name: &str| args.get(name).and_then(Value::as_str);
+            let path = if let Some(id) = arg("post") {
+                self.post(id)
+            } else if let Some(tag) = arg("tag") {
+                tag_url(tag)
+            } else if let Some(path) = arg("path") {
+                self.canonical_path(path)
+            } else {
+                return Err(tera::Error::msg(
+                    "url_for requires a 'post', 'tag' or 'path' argument",
+                ));
+            };
+            let absolute = args
+                .get("absolute")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            Ok(Value::String(if absolute {
+                self.absolute(&path)
+            } else {
+                path
+            }))
+        }
+    }
+}
+
+/// Link to the home page's list of posts with `tag`. The home page is `/`
+/// under either trailing slash policy.
+pub fn tag_url(tag: &str) -> String {
+    format!("/#{}", tag)
+}
+
+/// Resolves `url`, as found on the page at `page_url`, to an absolute URL.
+pub fn absolute_url(url: &str, page_url: &str) -> String {
+    let url = url.trim();
+    if url.is_empty() {
+        return page_url.to_string();
+    }
+    if let Some(rest) = url.strip_prefix("//") {
+        return format!("https://{}", rest);
+    }
+    if has_scheme(url) {
+        return url.to_string();
+    }
+    if let Some(path) = url.strip_prefix('/') {
+        return format!("{}/{}", BASE_URL, path);
+    }
+    if url.starts_with('#') || url.starts_with('?') {
+        return format!("{}{}", page_url, url);
+    }
+
+    // Relative path: resolve `.` and `..` against the directory of the page
+    let page_path = page_url.strip_prefix(BASE_URL).unwrap_or(page_url);
+    let mut segments: Vec<&str> = page_path.split('/').filter(|s| !s.is_empty()).collect();
+    if !page_path.ends_with('/') {
+        segments.pop();
+    }
+    let (path, suffix) = match url.find(['?', '#']) {
+        Some(i) => url.split_at(i),
+        None => (url, ""),
+    };
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let trailing_slash = if path.ends_with('/') { "/" } else { "" };
+    format!(
+        "{}/{}{}{}",
+        BASE_URL,
+        segments.join("/"),
+        trailing_slash,
+        suffix
+    )
+}
+
+/// Whether `url` starts with a scheme such as `https:`, `mailto:` or `data:`.
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(colon) => {
+            let scheme = &url[..colon];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Fails if any link in the emitted HTML points to a generated page by
+/// anything other than its canonical URL. Links to assets and to pages
+/// outside the output directory are not checked.
+pub fn check_internal_links(out_dir: &Path, urls: &SiteUrls) -> Result<()> {
+    let start = Instant::now();
+
+    let mut checked = 0;
+    let mut offending = Vec::new();
+    for entry in WalkDir::new(out_dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some("html")
+        {
+            continue;
+        }
+        let relative = path.strip_prefix(out_dir)?;
+        let served_url = served_url(relative);
+        let html = fs::read_to_string(path)?;
+        let markup = NON_MARKUP_BLOCK_RE.replace_all(&html, "");
+
+        for caps in HREF_RE.captures_iter(&markup) {
+            let href = caps.get(1).or(caps.get(2)).map_or("", |m| m.as_str());
+            // Links within the same page name no page
+            if href.is_empty() || href.starts_with(['#', '?']) {
+                continue;
+            }
+            let Some(link) = absolute_url(href, &served_url)
+                .strip_prefix(BASE_URL)
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            let link_path = link.split(['?', '#']).next().unwrap_or_default();
+            let Some(target) = page_file(out_dir, link_path) else {
+                continue;
+            };
+            checked += 1;
+            let canonical = urls.for_file(Path::new(&target));
+            if link_path != canonical {
+                offending.push(format!(
+                    "{}: {} (canonical: {})",
+                    relative.display(),
+                    href,
+                    canonical
+                ));
+            }
+        }
+    }
+
+    if !offending.is_empty() {
+        eprintln!();
+        for link in offending.iter().take(MAX_REPORTED_LINKS) {
+            eprintln!("  {}", link);
+        }
+        if offending.len() > MAX_REPORTED_LINKS {
+            eprintln!("  ... and {} more", offending.len() - MAX_REPORTED_LINKS);
+        }
+        bail!(
+            "{} of {} internal links are not in canonical form",
+            offending.len(),
+            checked
+        );
+    }
+
+    println!(
+        "✓ Checked {} internal links in {:.2}s",
+        checked,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Absolute URL a generated file is served at; directory pages are served
+/// with a trailing slash, which relative links resolve against.
+fn served_url(relative: &Path) -> String {
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    let path = relative.strip_suffix("index.html").unwrap_or(&relative);
+    format!("{}/{}", BASE_URL, path)
+}
+
+/// The HTML page in `out_dir` that serves `link_path`, relative to
+/// `out_dir`, or `None` if the link leads to an asset or nowhere.
+fn page_file(out_dir: &Path, link_path: &str) -> Option<String> {
+    let path = link_path.trim_start_matches('/');
+    let directory_page = format!("{}index.html", path);
+    let directory_page = if path.is_empty() || path.ends_with('/') {
+        directory_page
+    } else if out_dir.join(path).is_file() {
+        return path.ends_with(".html").then(|| path.to_string());
+    } else {
+        format!("{}/index.html", path)
+    };
+    out_dir
+        .join(&directory_page)
+        .is_file()
+        .then_some(directory_page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_internal_links, SiteUrls, TrailingSlash};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tera::{Function, Value};
+
+    #[test]
+    fn directory_pages_follow_the_trailing_slash_policy() {
+        let always = SiteUrls::new(TrailingSlash::Always);
+        let never = SiteUrls::new(TrailingSlash::Never);
+
+        assert_eq!(always.post("rust"), "/posts/rust/");
+        assert_eq!(never.post("rust"), "/posts/rust");
+        assert_eq!(never.for_file(Path::new("index.html")), "/");
+        assert_eq!(always.for_file(Path::new("search/index.html")), "/search/");
+        assert_eq!(
+            always.for_file(Path::new("impressum.html")),
+            "/impressum.html"
+        );
+    }
+
+    #[test]
+    fn hand_written_post_links_are_canonicalized() {
+        let urls = SiteUrls::new(TrailingSlash::Never);
+        let html = concat!(
+            r#"<a href="/posts/memetics.html">a</a> <a href='/posts/rag/#chunks'>b</a> "#,
+            r#"<a href="https://seanpedersen.github.io/posts/ipfs/">c</a> "#,
+            r#"<a href="/index.html#coding">d</a> <a href="https://example.com/posts/x/">e</a>"#
+        );
+
+        assert_eq!(
+            urls.canonicalize_links(html),
+            concat!(
+                r#"<a href="/posts/memetics">a</a> <a href='/posts/rag#chunks'>b</a> "#,
+                r#"<a href="https://seanpedersen.github.io/posts/ipfs">c</a> "#,
+                r#"<a href="/#coding">d</a> <a href="https://example.com/posts/x/">e</a>"#
+            )
+        );
+    }
+
+    #[test]
+    fn url_for_builds_post_tag_and_absolute_urls() {
+        let url_for = SiteUrls::new(TrailingSlash::Always).tera_function();
+        let call = |args: &[(&str, Value)]| {
+            let args: HashMap<String, Value> = args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect();
+            url_for.call(&args).unwrap()
+        };
+
+        assert_eq!(call(&[("post", "vae".into())]), "/posts/vae/");
+        assert_eq!(call(&[("tag", "ml".into())]), "/#ml");
+        assert_eq!(
+            call(&[("path", "/search".into()), ("absolute", true.into())]),
+            "https://seanpedersen.github.io/search/"
+        );
+        assert_eq!(call(&[("path", "/search/".into())]), "/search/");
+        assert_eq!(call(&[("path", "/rss.xml".into())]), "/rss.xml");
+    }
+
+    #[test]
+    fn link_check_rejects_non_canonical_links_to_pages() {
+        let out_dir =
+            std::env::temp_dir().join(format!("blog-builder-links-{}", std::process::id()));
+        fs::create_dir_all(out_dir.join("posts/a")).unwrap();
+        fs::write(
+            out_dir.join("posts/a/index.html"),
+            "<a href=\"../a\">self</a>",
+        )
+        .unwrap();
+        fs::write(
+            out_dir.join("index.html"),
+            "<a href=\"/posts/a\">a</a><link href=\"/rss.xml\"><a href=\"/gone\">x</a>",
+        )
+        .unwrap();
+
+        let never = check_internal_links(&out_dir, &SiteUrls::new(TrailingSlash::Never));
+        let always = check_internal_links(&out_dir, &SiteUrls::new(TrailingSlash::Always));
+        fs::remove_dir_all(&out_dir).unwrap();
+
+        assert!(never.is_ok());
+        assert_eq!(
+            always.unwrap_err().to_string(),
+            "2 of 2 internal links are not in canonical form"
+        );
+    }
+}
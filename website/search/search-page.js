@@ -0,0 +1,215 @@
+(function () {
+  'use strict';
+
+  // Results shown per query; snippets are fetched for these only
+  const MAX_RESULTS = 20;
+  // Matches where the query is only a prefix of an indexed term rank lower
+  const PREFIX_MATCH_FACTOR = 0.6;
+  // English stemming mostly removes short suffixes ("-ing", "-ed", "-s")
+  const MAX_STEM_SUFFIX_CHARS = 3;
+  const SNIPPET_CONTEXT_CHARS = 80;
+  const SNIPPET_LENGTH_CHARS = 220;
+
+  let meta = null;
+  const shardCache = new Map();
+  const textCache = new Map();
+  let latestQuery = '';
+
+  const input = document.getElementById('searchPageInput');
+  const status = document.getElementById('searchPageStatus');
+  const results = document.getElementById('searchPageResults');
+  if (!input || !status || !results) return;
+
+  function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+  }
+
+  function escapeRegExp(text) {
+    return text.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
+  }
+
+  // Mirrors the tokenizer in search_generation.rs, minus stemming
+  function tokenize(query) {
+    const stopwords = new Set(meta.stopwords);
+    return query
+      .toLowerCase()
+      .split(/[^\p{L}\p{N}]+/u)
+      .filter(word => word.length >= meta.min_token_chars && !stopwords.has(word));
+  }
+
+  function shardKey(token) {
+    return /^[0-9a-z]/.test(token) ? token[0] : '_';
+  }
+
+  function loadMeta() {
+    if (meta) return Promise.resolve(meta);
+    return fetch('/search/meta.json')
+      .then(response => response.json())
+      .then(data => {
+        meta = data;
+        return meta;
+      });
+  }
+
+  function loadShard(key) {
+    if (!meta.shards.includes(key)) return Promise.resolve({});
+    if (!shardCache.has(key)) {
+      shardCache.set(key, fetch(`/search/index-${key}.json`).then(response => response.json()));
+    }
+    return shardCache.get(key);
+  }
+
+  function loadText(id) {
+    if (!textCache.has(id)) {
+      textCache.set(id, fetch(`/search/text/${id}.txt`).then(response => response.text()));
+    }
+    return textCache.get(id);
+  }
+
+  // Relevance of an indexed (stemmed) term for a typed token, or 0
+  function matchFactor(term, token) {
+    if (term === token) return 1;
+    if (token.startsWith(term) && token.length - term.length <= MAX_STEM_SUFFIX_CHARS) return 1;
+    if (term.startsWith(token)) return PREFIX_MATCH_FACTOR;
+    return 0;
+  }
+
+  // Scores each document for one token; keeps the best matching term per document
+  function scoreToken(token, shard) {
+    const scores = new Map();
+    for (const term in shard) {
+      const factor = matchFactor(term, token);
+      if (!factor) continue;
+      const postings = shard[term];
+      const idf = Math.log(1 + meta.docs.length / postings.length);
+      for (const [doc, weight] of postings) {
+        const score = weight * factor * idf;
+        if (score > (scores.get(doc) || 0)) scores.set(doc, score);
+      }
+    }
+    return scores;
+  }
+
+  function search(tokens) {
+    return Promise.all(tokens.map(token => loadShard(shardKey(token)))).then(shards => {
+      let combined = null;
+      tokens.forEach((token, i) => {
+        const scores = scoreToken(token, shards[i]);
+        if (combined === null) {
+          combined = scores;
+          return;
+        }
+        // Every token has to match
+        const next = new Map();
+        for (const [doc, score] of combined) {
+          if (scores.has(doc)) next.set(doc, score + scores.get(doc));
+        }
+        combined = next;
+      });
+      return Array.from(combined || [])
+        .sort((a, b) => b[1] - a[1])
+        .slice(0, MAX_RESULTS)
+        .map(([doc]) => meta.docs[doc]);
+    });
+  }
+
+  function buildSnippet(text, tokens) {
+    const lower = text.toLowerCase();
+    let position = -1;
+    for (const token of tokens) {
+      const index = lower.indexOf(token);
+      if (index !== -1 && (position === -1 || index < position)) position = index;
+    }
+
+    let start = Math.max(0, position - SNIPPET_CONTEXT_CHARS);
+    if (start > 0) {
+      const space = text.indexOf(' ', start);
+      if (space !== -1 && space < position) start = space + 1;
+    }
+    let snippet = text.slice(start, start + SNIPPET_LENGTH_CHARS);
+    const lastSpace = snippet.lastIndexOf(' ');
+    if (start + SNIPPET_LENGTH_CHARS < text.length && lastSpace > 0) {
+      snippet = snippet.slice(0, lastSpace);
+    }
+
+    const pattern = new RegExp(`(${tokens.map(escapeRegExp).join('|')})`, 'gi');
+    const highlighted = snippet
+      .split(pattern)
+      .map((part, i) => (i % 2 === 1 ? `<mark>${escapeHtml(part)}</mark>` : escapeHtml(part)))
+      .join('');
+
+    return `${start > 0 ? '… ' : ''}${highlighted}${start + snippet.length < text.length ? ' …' : ''}`;
+  }
+
+  function renderResults(docs, tokens) {
+    results.innerHTML = docs
+      .map(doc => `
+        <li class="searchPageResult">
+          <a href="${escapeHtml(doc.url)}" class="searchPageResultTitle">${escapeHtml(doc.title)}</a>
+          <span class="searchPageResultDate">${escapeHtml(doc.date)}</span>
+          <p class="searchPageSnippet" data-post-id="${doc.id}"></p>
+        </li>
+      `)
+      .join('');
+
+    docs.forEach(doc => {
+      loadText(doc.id)
+        .then(text => {
+          const snippet = results.querySelector(`[data-post-id="${doc.id}"]`);
+          if (snippet) snippet.innerHTML = buildSnippet(text, tokens);
+        })
+        .catch(() => {});
+    });
+  }
+
+  function runQuery(query) {
+    latestQuery = query;
+    const url = new URL(window.location.href);
+    if (query) {
+      url.searchParams.set('q', query);
+    } else {
+      url.searchParams.delete('q');
+    }
+    history.replaceState(null, '', url);
+
+    loadMeta()
+      .then(() => {
+        const tokens = tokenize(query);
+        if (tokens.length === 0) return [[], tokens];
+        return search(tokens).then(docs => [docs, tokens]);
+      })
+      .then(([docs, tokens]) => {
+        // A slower, older query must not overwrite a newer one
+        if (query !== latestQuery) return;
+        renderResults(docs, tokens);
+        if (tokens.length === 0) {
+          status.textContent = '';
+        } else {
+          status.textContent = docs.length === 1 ? '1 result' : `${docs.length} results`;
+        }
+      })
+      .catch(error => {
+        console.error('Search failed:', error);
+        status.textContent = 'Search index could not be loaded.';
+      });
+  }
+
+  let debounceTimer = null;
+  input.addEventListener('input', function () {
+    clearTimeout(debounceTimer);
+    debounceTimer = setTimeout(() => runQuery(input.value.trim()), 120);
+  });
+
+  document.querySelector('.searchPageForm').addEventListener('submit', function (e) {
+    e.preventDefault();
+    runQuery(input.value.trim());
+  });
+
+  const initialQuery = new URLSearchParams(window.location.search).get('q') || '';
+  if (initialQuery) {
+    input.value = initialQuery;
+    runQuery(initialQuery.trim());
+  }
+})();